@@ -54,11 +54,7 @@ fn main() {
 
 impl Describe {
     fn contains_tags(&self) -> bool {
-        match &self.tag {
-            &Tag::Compound(..) |
-            &Tag::List(..) => true,
-            _ => false
-        }
+        matches!(self.tag, Tag::Compound(..) | Tag::List(..))
     }
 
     fn indent(indent_level: usize, f: &mut fmt::Formatter) -> fmt::Result {
@@ -78,59 +74,59 @@ impl Display for Describe {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match &self.tag {
             Tag::Byte(x) => {
-                if None == self.name {
+                if self.name.is_none() {
                     f.write_fmt(format_args!("{} ", self.tag.type_name()))?;
                 }
                 f.write_fmt(format_args!("{}", x))
             },
             Tag::Short(x) => {
-                if None == self.name {
+                if self.name.is_none() {
                     f.write_fmt(format_args!("{} ", self.tag.type_name()))?;
                 }
                 f.write_fmt(format_args!("{}", x))
             },
             Tag::Int(x) => {
-                if None == self.name {
+                if self.name.is_none() {
                     f.write_fmt(format_args!("{} ", self.tag.type_name()))?;
                 }
                 f.write_fmt(format_args!("{}", x))
             },
             Tag::Long(x) => {
-                if None == self.name {
+                if self.name.is_none() {
                     f.write_fmt(format_args!("{} ", self.tag.type_name()))?;
                 }
                 f.write_fmt(format_args!("{}", x))
             },
             Tag::Float(x) => {
-                if None == self.name {
+                if self.name.is_none() {
                     f.write_fmt(format_args!("{} ", self.tag.type_name()))?;
                 }
                 f.write_fmt(format_args!("{}", x))
             },
             Tag::Double(x) => {
-                if None == self.name {
+                if self.name.is_none() {
                     f.write_fmt(format_args!("{} ", self.tag.type_name()))?;
                 }
                 f.write_fmt(format_args!("{}", x))
             },
             Tag::ByteArray(ref contents) => {
-                if None == self.name {
+                if self.name.is_none() {
                     f.write_fmt(format_args!("ByteArray : {} bytes", contents.len()))?;
                 }
 
                 let mut counter = 0;
-                let mut content_iter = contents.split(|_| {
+                let content_iter = contents.split(|_| {
                     counter += 1;
                     counter % 16 == 0
-                }).peekable();
+                });
 
-                while let Some(chunk) = content_iter.next() {
+                for chunk in content_iter {
                     f.write_char('\n')?;
                     Describe::indent(self.indent_level + 1, f)?;
                     let mut chunk_iter = chunk.iter().peekable();
                     while let Some(b) = chunk_iter.next() {
                         f.write_fmt(format_args!("{:02X}", b))?;
-                        if let Some(..) = chunk_iter.peek() {
+                        if chunk_iter.peek().is_some() {
                             f.write_str(", ")?;
                         }
                     }
@@ -138,13 +134,13 @@ impl Display for Describe {
                 Ok(())
             }
             Tag::String(text) => {
-                if None == self.name {
+                if self.name.is_none() {
                     f.write_fmt(format_args!("{} ", self.tag.type_name()))?;
                 }
                 f.write_fmt(format_args!("'{}'", text))
             },
             Tag::List(ref tag_list) => {
-                if None == self.name {
+                if self.name.is_none() {
                     f.write_fmt(format_args!("List : {} entry\n", tag_list.len()))?;
                 }
 
@@ -165,7 +161,7 @@ impl Display for Describe {
                 })
             }
             Tag::Compound(ref tag_mappings) => {
-                if None == self.name {
+                if self.name.is_none() {
                     f.write_fmt(format_args!("Compound : {} entry\n", tag_mappings.len()))?;
                 }
 
@@ -199,7 +195,7 @@ impl Display for Describe {
                 })
             },
             Tag::IntArray(ref contents) => {
-                if None == self.name {
+                if self.name.is_none() {
                     f.write_fmt(format_args!("IntArray : {} entry\n", contents.len()))?;
                 }
 
@@ -210,7 +206,7 @@ impl Display for Describe {
                 Ok(())
             },
             Tag::LongArray(ref contents) => {
-                if None == self.name {
+                if self.name.is_none() {
                     f.write_fmt(format_args!("LongArray : {} entry\n", contents.len()))?;
                 }
 
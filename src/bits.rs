@@ -0,0 +1,329 @@
+//! Arrays of small unsigned integers packed into the longs of a LongArray, as used
+//! for palette indices in Litematica regions and chunk sections, and for heightmaps.
+//! Light levels and the data values of legacy blocks are instead packed two per byte
+//! into a ByteArray.
+use errors::*;
+
+use fields;
+
+/// First data version to use `Layout::Padded` for chunk data, 20w17a
+pub const PADDED_DATA_VERSION: i32 = 2529;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// How entries are packed into longs, starting from the least significant bit
+pub enum Layout {
+    /// Entries are packed tightly and may span two longs, as used by Litematica and
+    /// by chunks before 1.16
+    Spanning,
+    /// Only as many entries as fit whole are packed into each long, leaving the
+    /// remaining high bits unused, as used by chunks since 1.16
+    Padded,
+}
+
+impl Layout {
+    /// The layout used for chunk data of the given data version
+    pub fn for_data_version(data_version: i32) -> Self {
+        if data_version >= PADDED_DATA_VERSION {
+            Layout::Padded
+        } else {
+            Layout::Spanning
+        }
+    }
+
+    /// Number of longs needed to hold `len` entries of `bits` bits each, or `None` if
+    /// `bits` is not between 1 and 64 or the number of bits overflows
+    pub fn longs_needed(self, bits: u32, len: usize) -> Option<usize> {
+        if !(1..=64).contains(&bits) {
+            return None;
+        }
+        match self {
+            Layout::Spanning => len.checked_mul(bits as usize)?.checked_add(63).map(|bits| bits / 64),
+            Layout::Padded => Some(len.div_ceil(64 / bits as usize)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// A fixed number of entries of `bits` bits each, packed into longs
+pub struct BitStorage {
+    layout: Layout,
+    bits: u32,
+    len: usize,
+    data: Vec<i64>,
+}
+
+impl BitStorage {
+    /// Create a storage holding `len` zero entries, failing if `bits` is not between
+    /// 1 and 32 or there are too many entries to count their bits
+    pub fn new(layout: Layout, bits: u32, len: usize) -> Result<Self> {
+        if !(1..=32).contains(&bits) {
+            return Err(fields::invalid("bits", "between 1 and 32 bits per entry"));
+        }
+        let longs = layout.longs_needed(bits, len).ok_or(Error::CollectionTooLong(len))?;
+        Ok(BitStorage { layout, bits, len, data: vec![0; longs] })
+    }
+
+    /// Use the longs of a LongArray as the storage, which must have exactly the length
+    /// required to hold `len` entries of `bits` bits
+    pub fn from_longs(layout: Layout, bits: u32, len: usize, data: Vec<i64>) -> Result<Self> {
+        if !(1..=32).contains(&bits) || layout.longs_needed(bits, len) != Some(data.len()) {
+            return Err(fields::invalid("LongArray", "the length required by the bits per entry"));
+        }
+        Ok(BitStorage { layout, bits, len, data })
+    }
+
+    /// Pack values into a new storage
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bits` is not between 1 and 32 or a value does not fit into `bits` bits
+    pub fn from_values(layout: Layout, bits: u32, values: &[u32]) -> Self {
+        // Entries held in memory as u32 always fit into longs of at most 32 bits each
+        let mut storage = BitStorage::new(layout, bits, values.len()).expect("entries must be between 1 and 32 bits");
+        for (index, &value) in values.iter().enumerate() {
+            storage.set(index, value);
+        }
+        storage
+    }
+
+    pub fn layout(&self) -> Layout {
+        self.layout
+    }
+
+    pub fn bits(&self) -> u32 {
+        self.bits
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn as_longs(&self) -> &[i64] {
+        &self.data
+    }
+
+    pub fn into_longs(self) -> Vec<i64> {
+        self.data
+    }
+
+    fn mask(&self) -> u64 {
+        (1u64 << self.bits) - 1
+    }
+
+    /// The long holding the start of an entry and the offset of the entry within it
+    fn locate(&self, index: usize) -> (usize, u32) {
+        match self.layout {
+            Layout::Spanning => {
+                let bit = index * self.bits as usize;
+                (bit / 64, (bit % 64) as u32)
+            },
+            Layout::Padded => {
+                let per_long = 64 / self.bits as usize;
+                (index / per_long, (index % per_long) as u32 * self.bits)
+            },
+        }
+    }
+
+    /// Get the entry at `index`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds
+    pub fn get(&self, index: usize) -> u32 {
+        assert!(index < self.len, "index {} out of bounds for {} entries", index, self.len);
+        let (long, offset) = self.locate(index);
+        let mut value = self.data[long] as u64 >> offset;
+        if offset + self.bits > 64 {
+            value |= (self.data[long + 1] as u64) << (64 - offset);
+        }
+        (value & self.mask()) as u32
+    }
+
+    /// Set the entry at `index`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds or `value` does not fit into the entry
+    pub fn set(&mut self, index: usize, value: u32) {
+        assert!(index < self.len, "index {} out of bounds for {} entries", index, self.len);
+        assert!(u64::from(value) <= self.mask(), "{} does not fit into {} bits", value, self.bits);
+        let (long, offset) = self.locate(index);
+        let mask = self.mask();
+        let value = u64::from(value);
+
+        let low = self.data[long] as u64 & !(mask << offset) | value << offset;
+        self.data[long] = low as i64;
+        if offset + self.bits > 64 {
+            let shift = 64 - offset;
+            let high = self.data[long + 1] as u64 & !(mask >> shift) | value >> shift;
+            self.data[long + 1] = high as i64;
+        }
+    }
+
+    pub fn iter<'a>(&'a self) -> impl Iterator<Item = u32> + 'a {
+        (0..self.len).map(move |index| self.get(index))
+    }
+
+    /// Repack the entries using a different number of bits, such as when a palette has
+    /// grown beyond what the current entries can index
+    ///
+    /// # Panics
+    ///
+    /// Panics if an entry does not fit into `bits` bits
+    pub fn resize(&mut self, bits: u32) {
+        if bits != self.bits {
+            let values: Vec<u32> = self.iter().collect();
+            *self = BitStorage::from_values(self.layout, bits, &values);
+        }
+    }
+
+    /// Widen the entries if needed to index into a palette of `len` entries, never
+    /// using fewer than `min` bits
+    pub fn grow_for_palette(&mut self, len: usize, min: u32) {
+        let bits = bits_for(len, min);
+        if bits > self.bits {
+            self.resize(bits);
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// Entries of four bits packed two per byte of a ByteArray, the entry at an even
+/// index in the low half of its byte
+pub struct NibbleArray {
+    data: Vec<i8>,
+}
+
+impl NibbleArray {
+    /// Create an array holding `len` zero entries, rounded up to an even number
+    pub fn new(len: usize) -> Self {
+        NibbleArray { data: vec![0; len.div_ceil(2)] }
+    }
+
+    /// Use the bytes of a ByteArray as the entries
+    pub fn from_bytes(data: Vec<i8>) -> Self {
+        NibbleArray { data }
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len() * 2
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn as_bytes(&self) -> &[i8] {
+        &self.data
+    }
+
+    pub fn into_bytes(self) -> Vec<i8> {
+        self.data
+    }
+
+    /// Get the entry at `index`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds
+    pub fn get(&self, index: usize) -> u8 {
+        let byte = self.data[index >> 1] as u8;
+        if index & 1 == 0 { byte & 0x0f } else { byte >> 4 }
+    }
+
+    /// Set the entry at `index`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds or `value` does not fit into four bits
+    pub fn set(&mut self, index: usize, value: u8) {
+        assert!(value <= 0x0f, "{} does not fit into 4 bits", value);
+        let byte = self.data[index >> 1] as u8;
+        let byte = if index & 1 == 0 { byte & 0xf0 | value } else { byte & 0x0f | value << 4 };
+        self.data[index >> 1] = byte as i8;
+    }
+
+    pub fn iter<'a>(&'a self) -> impl Iterator<Item = u8> + 'a {
+        (0..self.len()).map(move |index| self.get(index))
+    }
+}
+
+/// Bits needed to store indices into a palette of `len` entries, at least `min`
+pub fn bits_for(len: usize, min: u32) -> u32 {
+    let bits = usize::BITS - len.saturating_sub(1).leading_zeros();
+    bits.max(min)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bit_storage() {
+        // 5 bit entries, so the 13th entry spans the first and second long
+        let values: Vec<u32> = (0..40).map(|value| (value * 7) % 32).collect();
+        let storage = BitStorage::from_values(Layout::Spanning, 5, &values);
+        assert_eq!(storage.as_longs().len(), 4);
+        assert_eq!(storage.iter().collect::<Vec<_>>(), values);
+
+        let reread = BitStorage::from_longs(Layout::Spanning, 5, values.len(), storage.clone().into_longs()).unwrap();
+        assert_eq!(reread, storage);
+        assert!(BitStorage::from_longs(Layout::Spanning, 5, values.len(), vec![0; 3]).is_err());
+        assert!(BitStorage::from_longs(Layout::Spanning, 32, usize::MAX / 2, vec![0; 3]).is_err());
+        assert!(BitStorage::new(Layout::Spanning, 32, usize::MAX / 2).is_err());
+        for &bits in &[0, 33, 65] {
+            assert!(BitStorage::new(Layout::Padded, bits, 16).is_err());
+            assert!(BitStorage::from_longs(Layout::Padded, bits, 16, vec![0; 1]).is_err());
+        }
+        assert_eq!(Layout::Padded.longs_needed(0, 16), None);
+        assert_eq!(Layout::Padded.longs_needed(65, 16), None);
+        assert_eq!(Layout::Padded.longs_needed(64, 16), Some(16));
+
+        // Only 12 entries fit into each long, so 40 of them need 4 longs
+        let mut storage = BitStorage::from_values(Layout::Padded, 5, &values);
+        assert_eq!(storage.as_longs().len(), 4);
+        assert_eq!(storage.as_longs()[0] as u64 >> 60, 0);
+        assert_eq!(storage.iter().collect::<Vec<_>>(), values);
+
+        storage.grow_for_palette(32, 4);
+        assert_eq!(storage.bits(), 5);
+        storage.grow_for_palette(33, 4);
+        assert_eq!((storage.bits(), storage.as_longs().len()), (6, 4));
+        assert_eq!(storage.iter().collect::<Vec<_>>(), values);
+        storage.set(39, 63);
+        assert_eq!(storage.get(39), 63);
+
+        assert_eq!(bits_for(1, 2), 2);
+        assert_eq!(bits_for(5, 2), 3);
+        assert_eq!(bits_for(16, 4), 4);
+        assert_eq!(bits_for(17, 4), 5);
+    }
+
+    #[test]
+    fn test_heightmap() {
+        // A 1.16+ heightmap holds 256 entries of 9 bits, 7 per long
+        let heights: Vec<u32> = (0..256).map(|index| 64 + index % 100).collect();
+        let storage = BitStorage::from_values(Layout::for_data_version(2586), 9, &heights);
+        assert_eq!(storage.as_longs().len(), 37);
+        let storage = BitStorage::from_values(Layout::for_data_version(2230), 9, &heights);
+        assert_eq!(storage.as_longs().len(), 36);
+        assert_eq!(storage.iter().collect::<Vec<_>>(), heights);
+    }
+
+    #[test]
+    fn test_nibble_array() {
+        let mut nibbles = NibbleArray::new(5);
+        assert_eq!(nibbles.len(), 6);
+        nibbles.set(0, 0x3);
+        nibbles.set(1, 0xf);
+        nibbles.set(4, 0x9);
+        assert_eq!(nibbles.as_bytes(), &[0xf3u8 as i8, 0x00, 0x09]);
+        nibbles.set(1, 0x1);
+        assert_eq!(nibbles.iter().collect::<Vec<_>>(), vec![0x3, 0x1, 0, 0, 0x9, 0]);
+        assert_eq!(NibbleArray::from_bytes(nibbles.clone().into_bytes()), nibbles);
+    }
+}
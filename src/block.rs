@@ -0,0 +1,123 @@
+//! Block states as found in the palettes of structures, schematics and chunks
+use std::fmt;
+use std::str::FromStr;
+use errors::*;
+
+use fields;
+use tag::Tag;
+
+pub const AIR: &str = "minecraft:air";
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+/// A block and the values of its properties, such as `minecraft:oak_stairs[facing=north]`
+pub struct BlockState {
+    pub name: String,
+    /// Property names and values, in the order they were read
+    pub properties: Vec<(String, String)>,
+}
+
+impl BlockState {
+    pub fn new<S: Into<String>>(name: S) -> Self {
+        BlockState { name: name.into(), properties: Vec::new() }
+    }
+
+    pub fn air() -> Self {
+        BlockState::new(AIR)
+    }
+
+    pub fn with_property<K: Into<String>, V: Into<String>>(mut self, name: K, value: V) -> Self {
+        self.properties.push((name.into(), value.into()));
+        self
+    }
+
+    pub fn property(&self, name: &str) -> Option<&str> {
+        self.properties.iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Read the `{Name, Properties}` Compound used by palettes
+    pub fn from_tag(tag: &Tag) -> Result<Self> {
+        let mut state = BlockState::new(fields::string(tag, "Name")?);
+        if let Some(properties) = tag.get("Properties") {
+            let properties = properties.entries().ok_or_else(|| fields::invalid("Properties", "a Compound"))?;
+            for (name, value) in properties {
+                let value = value.as_str().ok_or_else(|| fields::invalid(name, "a String"))?;
+                state.properties.push((name.to_owned(), value.to_owned()));
+            }
+        }
+        Ok(state)
+    }
+
+    pub fn to_tag(&self) -> Tag {
+        let mut tag = Tag::compound(vec![("Name", Tag::from(self.name.as_str()))]);
+        if !self.properties.is_empty() {
+            let properties = self.properties.iter()
+                .map(|(name, value)| (name.as_str(), Tag::from(value.as_str())));
+            tag.insert("Properties", Tag::compound(properties));
+        }
+        tag
+    }
+}
+
+impl fmt::Display for BlockState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.name)?;
+        if !self.properties.is_empty() {
+            f.write_str("[")?;
+            for (index, (name, value)) in self.properties.iter().enumerate() {
+                if index > 0 {
+                    f.write_str(",")?;
+                }
+                write!(f, "{}={}", name, value)?;
+            }
+            f.write_str("]")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for BlockState {
+    type Err = Error;
+
+    /// Parse the string form used by commands and schematic palettes
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || Error::InvalidBlockState(s.to_owned());
+        let (name, properties) = match s.find('[') {
+            Some(start) if s.ends_with(']') => (&s[..start], Some(&s[start + 1..s.len() - 1])),
+            Some(..) => return Err(invalid()),
+            None => (s, None),
+        };
+        if name.is_empty() {
+            return Err(invalid());
+        }
+
+        let mut state = BlockState::new(name);
+        for property in properties.iter().flat_map(|properties| properties.split(',')).filter(|p| !p.is_empty()) {
+            let mut parts = property.splitn(2, '=');
+            match (parts.next(), parts.next()) {
+                (Some(name), Some(value)) if !name.is_empty() => state = state.with_property(name.trim(), value.trim()),
+                _ => return Err(invalid()),
+            }
+        }
+        Ok(state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_block_state() {
+        let state: BlockState = "minecraft:oak_stairs[facing=north,half=top]".parse().unwrap();
+        assert_eq!(state.name, "minecraft:oak_stairs");
+        assert_eq!(state.property("half"), Some("top"));
+        assert_eq!(state.to_string(), "minecraft:oak_stairs[facing=north,half=top]");
+        assert_eq!(BlockState::from_tag(&state.to_tag()).unwrap(), state);
+
+        assert_eq!("minecraft:stone".parse::<BlockState>().unwrap(), BlockState::new("minecraft:stone"));
+        assert!("minecraft:stone[facing".parse::<BlockState>().is_err());
+        assert!("[a=b]".parse::<BlockState>().is_err());
+    }
+}
@@ -0,0 +1,418 @@
+//! Chunks as stored in the region files of worlds saved by Minecraft 1.18 and later.
+//!
+//! A chunk is split into sections of 16x16x16 blocks, each holding a palette of block
+//! states and a palette of biomes for its 4x4x4 cells. Palette indices are packed into
+//! long arrays using `Layout::Padded`, and left out when a palette has a single entry.
+//!
+//! Chunks saved before 1.13 are read by `legacy_chunk`. Chunks of 1.13 to 1.17, from
+//! `legacy_chunk::FLATTENING_DATA_VERSION` up to `MIN_DATA_VERSION`, are not supported
+//! by either. They keep everything in a `Level` Compound with sections holding a
+//! `Palette` and `BlockStates`, packed in the layout given by `Layout::for_data_version`,
+//! and can be read as plain tags.
+use std::convert::TryFrom;
+use errors::*;
+
+use bits;
+use bits::BitStorage;
+use bits::Layout;
+use bits::NibbleArray;
+use block::BlockState;
+use fields;
+use tag::Tag;
+
+/// First data version using this layout, 21w43a, which moved everything out of the
+/// `Level` Compound and gave chunks a variable height
+pub const MIN_DATA_VERSION: i32 = 2844;
+
+/// Blocks of each section
+pub const SECTION_BLOCKS: usize = 16 * 16 * 16;
+const SECTION_BIOMES: usize = 4 * 4 * 4;
+const MIN_BLOCK_BITS: u32 = 4;
+const MIN_BIOME_BITS: u32 = 1;
+const HEIGHTMAP_ENTRIES: usize = 16 * 16;
+
+/// Entries of a chunk which are read and written by `Chunk` itself
+const KNOWN: &[&str] = &[
+    "DataVersion", "xPos", "zPos", "yPos", "Status", "LastUpdate", "InhabitedTime",
+    "sections", "block_entities", "Heightmaps", "structures",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chunk {
+    pub data_version: i32,
+    /// Chunk coordinates, which are block coordinates divided by 16
+    pub x: i32,
+    pub z: i32,
+    /// Index of the lowest section
+    pub y: i32,
+    pub status: String,
+    /// Game tick the chunk was last saved at
+    pub last_update: i64,
+    /// Ticks players have spent in the chunk
+    pub inhabited_time: i64,
+    pub sections: Vec<Section>,
+    pub block_entities: Vec<Tag>,
+    /// Long arrays of each heightmap, see `Chunk::heightmap`
+    pub heightmaps: Vec<(String, Vec<i64>)>,
+    pub structures: Option<Tag>,
+    /// Every other entry of the chunk, such as scheduled ticks, kept as it was read
+    pub other: Vec<(String, Tag)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Section {
+    pub y: i8,
+    /// Absent from sections which only hold light
+    pub blocks: Option<PalettedContainer<BlockState>>,
+    pub biomes: Option<PalettedContainer<String>>,
+    pub block_light: Option<NibbleArray>,
+    pub sky_light: Option<NibbleArray>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// A palette and the index into it of every entry. When the palette holds a single
+/// value there is no need for any indices.
+pub struct PalettedContainer<T> {
+    pub palette: Vec<T>,
+    pub data: Option<BitStorage>,
+    len: usize,
+    min_bits: u32,
+}
+
+impl<T: PartialEq> PalettedContainer<T> {
+    /// Create a container where every entry is `value`
+    pub fn new(value: T, len: usize, min_bits: u32) -> Self {
+        PalettedContainer { palette: vec![value], data: None, len, min_bits }
+    }
+
+    fn from_tag<F>(tag: &Tag, len: usize, min_bits: u32, entry: F) -> Result<Self>
+        where F: Fn(&Tag) -> Result<T>
+    {
+        let palette = fields::list(tag, "palette")?.iter().map(entry).collect::<Result<Vec<_>>>()?;
+        if palette.is_empty() {
+            return Err(fields::invalid("palette", "at least one entry"));
+        }
+        let data = match tag.get("data") {
+            Some(..) if palette.len() > 1 => {
+                let bits = bits::bits_for(palette.len(), min_bits);
+                let data = fields::long_array(tag, "data")?.to_vec();
+                let data = BitStorage::from_longs(Layout::Padded, bits, len, data)?;
+                if data.iter().any(|index| index as usize >= palette.len()) {
+                    return Err(fields::invalid("data", "indices into the palette"));
+                }
+                Some(data)
+            },
+            _ if palette.len() > 1 => return Err(Error::MissingTag(String::from("data"))),
+            _ => None,
+        };
+        Ok(PalettedContainer { palette, data, len, min_bits })
+    }
+
+    fn to_tag<F: Fn(&T) -> Tag>(&self, entry: F) -> Tag {
+        let mut tag = Tag::compound(vec![("palette", Tag::List(self.palette.iter().map(entry).collect()))]);
+        if let Some(ref data) = self.data {
+            tag.insert("data", Tag::LongArray(data.as_longs().to_vec()));
+        }
+        tag
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds
+    pub fn get(&self, index: usize) -> &T {
+        assert!(index < self.len, "index {} out of bounds for {} entries", index, self.len);
+        match self.data {
+            Some(ref data) => &self.palette[data.get(index) as usize],
+            None => &self.palette[0],
+        }
+    }
+
+    /// Set an entry, adding the value to the palette and widening the indices when needed
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds
+    pub fn set(&mut self, index: usize, value: T) {
+        assert!(index < self.len, "index {} out of bounds for {} entries", index, self.len);
+        let palette_index = match self.palette.iter().position(|existing| *existing == value) {
+            Some(palette_index) => palette_index,
+            None => {
+                self.palette.push(value);
+                match self.data {
+                    Some(ref mut data) => data.grow_for_palette(self.palette.len(), self.min_bits),
+                    None => {
+                        let bits = bits::bits_for(self.palette.len(), self.min_bits);
+                        let data = BitStorage::new(Layout::Padded, bits, self.len).expect("padded storage never overflows");
+                        self.data = Some(data);
+                    },
+                }
+                self.palette.len() - 1
+            },
+        };
+        if let Some(ref mut data) = self.data {
+            data.set(index, palette_index as u32);
+        }
+    }
+}
+
+impl Section {
+    /// Create a section filled with air in the plains biome
+    pub fn new(y: i8) -> Self {
+        Section {
+            y,
+            blocks: Some(PalettedContainer::new(BlockState::air(), SECTION_BLOCKS, MIN_BLOCK_BITS)),
+            biomes: Some(PalettedContainer::new(String::from("minecraft:plains"), SECTION_BIOMES, MIN_BIOME_BITS)),
+            block_light: None,
+            sky_light: None,
+        }
+    }
+
+    pub fn from_tag(tag: &Tag) -> Result<Self> {
+        let biome = |tag: &Tag| tag.as_str().map(str::to_owned).ok_or_else(|| fields::invalid("palette", "Strings"));
+        Ok(Section {
+            y: i8::try_from(fields::int(tag, "Y")?).map_err(|_| fields::invalid("Y", "a Byte"))?,
+            blocks: match tag.get("block_states") {
+                Some(states) => Some(PalettedContainer::from_tag(states, SECTION_BLOCKS, MIN_BLOCK_BITS, BlockState::from_tag)?),
+                None => None,
+            },
+            biomes: match tag.get("biomes") {
+                Some(biomes) => Some(PalettedContainer::from_tag(biomes, SECTION_BIOMES, MIN_BIOME_BITS, biome)?),
+                None => None,
+            },
+            block_light: fields::optional(tag, "BlockLight", |tag, name| fields::nibble_array(tag, name, SECTION_BLOCKS))?,
+            sky_light: fields::optional(tag, "SkyLight", |tag, name| fields::nibble_array(tag, name, SECTION_BLOCKS))?,
+        })
+    }
+
+    pub fn to_tag(&self) -> Tag {
+        let mut tag = Tag::compound(vec![("Y", Tag::Byte(self.y))]);
+        if let Some(ref blocks) = self.blocks {
+            tag.insert("block_states", blocks.to_tag(BlockState::to_tag));
+        }
+        if let Some(ref biomes) = self.biomes {
+            tag.insert("biomes", biomes.to_tag(|biome| Tag::from(biome.as_str())));
+        }
+        if let Some(ref light) = self.block_light {
+            tag.insert("BlockLight", Tag::ByteArray(light.as_bytes().to_vec()));
+        }
+        if let Some(ref light) = self.sky_light {
+            tag.insert("SkyLight", Tag::ByteArray(light.as_bytes().to_vec()));
+        }
+        tag
+    }
+
+    /// The block at coordinates within the section, `None` unless each is between 0 and 15
+    pub fn block(&self, x: usize, y: usize, z: usize) -> Option<&BlockState> {
+        if x > 15 || y > 15 || z > 15 {
+            return None;
+        }
+        self.blocks.as_ref().map(|blocks| blocks.get((y * 16 + z) * 16 + x))
+    }
+
+    /// The biome of the cell holding coordinates within the section
+    pub fn biome(&self, x: usize, y: usize, z: usize) -> Option<&str> {
+        if x > 15 || y > 15 || z > 15 {
+            return None;
+        }
+        self.biomes.as_ref().map(|biomes| biomes.get((y / 4 * 4 + z / 4) * 4 + x / 4).as_str())
+    }
+}
+
+impl Chunk {
+    /// Read a chunk as stored in a region file
+    pub fn from_tag(tag: &Tag) -> Result<Self> {
+        let data_version = fields::int(tag, "DataVersion")?;
+        if data_version < MIN_DATA_VERSION || tag.get("Level").is_some() {
+            return Err(Error::UnsupportedFormat("chunk saved before 1.18"));
+        }
+
+        let mut heightmaps = Vec::new();
+        if let Some(maps) = tag.get("Heightmaps") {
+            for (name, map) in maps.entries().ok_or_else(|| fields::invalid("Heightmaps", "a Compound"))? {
+                let map = map.as_long_array().ok_or_else(|| fields::invalid(name, "a LongArray"))?;
+                heightmaps.push((name.to_owned(), map.to_vec()));
+            }
+        }
+
+        Ok(Chunk {
+            data_version,
+            x: fields::int(tag, "xPos")?,
+            z: fields::int(tag, "zPos")?,
+            y: fields::int(tag, "yPos")?,
+            status: fields::string(tag, "Status")?.to_owned(),
+            last_update: tag.get("LastUpdate").map_or(Ok(0), |_| fields::long(tag, "LastUpdate"))?,
+            inhabited_time: tag.get("InhabitedTime").map_or(Ok(0), |_| fields::long(tag, "InhabitedTime"))?,
+            sections: fields::list(tag, "sections")?.iter().map(Section::from_tag).collect::<Result<_>>()?,
+            block_entities: match tag.get("block_entities") {
+                Some(..) => fields::list(tag, "block_entities")?.to_vec(),
+                None => Vec::new(),
+            },
+            heightmaps,
+            structures: tag.get("structures").cloned(),
+            other: fields::others(tag, KNOWN),
+        })
+    }
+
+    pub fn to_tag(&self) -> Tag {
+        let mut tag = Tag::compound(vec![
+            ("DataVersion", Tag::Int(self.data_version)),
+            ("xPos", Tag::Int(self.x)),
+            ("zPos", Tag::Int(self.z)),
+            ("yPos", Tag::Int(self.y)),
+            ("Status", Tag::from(self.status.as_str())),
+            ("LastUpdate", Tag::Long(self.last_update)),
+            ("InhabitedTime", Tag::Long(self.inhabited_time)),
+            ("sections", Tag::List(self.sections.iter().map(Section::to_tag).collect())),
+            ("block_entities", Tag::List(self.block_entities.clone())),
+            ("Heightmaps", Tag::compound(self.heightmaps.iter()
+                .map(|(name, map)| (name.as_str(), Tag::LongArray(map.clone()))))),
+        ]);
+        if let Some(ref structures) = self.structures {
+            tag.insert("structures", structures.clone());
+        }
+        for (name, other) in &self.other {
+            tag.insert(name.as_str(), other.clone());
+        }
+        tag
+    }
+
+    pub fn section(&self, y: i8) -> Option<&Section> {
+        self.sections.iter().find(|section| section.y == y)
+    }
+
+    pub fn section_mut(&mut self, y: i8) -> Option<&mut Section> {
+        self.sections.iter_mut().find(|section| section.y == y)
+    }
+
+    /// The block at world coordinates, if the chunk holds them
+    pub fn block(&self, x: i32, y: i32, z: i32) -> Option<&BlockState> {
+        if x >> 4 != self.x || z >> 4 != self.z {
+            return None;
+        }
+        self.section(i8::try_from(y >> 4).ok()?)?.block((x & 15) as usize, (y & 15) as usize, (z & 15) as usize)
+    }
+
+    /// The biome at world coordinates, if the chunk holds them
+    pub fn biome(&self, x: i32, y: i32, z: i32) -> Option<&str> {
+        if x >> 4 != self.x || z >> 4 != self.z {
+            return None;
+        }
+        self.section(i8::try_from(y >> 4).ok()?)?.biome((x & 15) as usize, (y & 15) as usize, (z & 15) as usize)
+    }
+
+    /// Set the block at world coordinates, creating its section if needed. Heightmaps
+    /// and light are left as they are, Minecraft recomputes them when loading.
+    pub fn set_block(&mut self, x: i32, y: i32, z: i32, state: BlockState) -> Result<()> {
+        let section_y = match i8::try_from(y >> 4) {
+            Ok(section_y) if x >> 4 == self.x && z >> 4 == self.z => section_y,
+            _ => return Err(Error::OutOfBounds { x, y, z }),
+        };
+        if self.section(section_y).is_none() {
+            self.sections.push(Section::new(section_y));
+            self.sections.sort_by_key(|section| section.y);
+        }
+        let section = self.section_mut(section_y).expect("section was just created");
+        let blocks = section.blocks.get_or_insert_with(|| PalettedContainer::new(BlockState::air(), SECTION_BLOCKS, MIN_BLOCK_BITS));
+        blocks.set((((y & 15) * 16 + (z & 15)) * 16 + (x & 15)) as usize, state);
+        Ok(())
+    }
+
+    /// Unpack a heightmap, such as `MOTION_BLOCKING`, for a world `height` blocks tall.
+    /// Heights are counted from the bottom of the world.
+    pub fn heightmap(&self, name: &str, height: u32) -> Option<Result<BitStorage>> {
+        let bits = bits::bits_for(height as usize + 1, 1);
+        self.heightmaps.iter()
+            .find(|(map, _)| map == name)
+            .map(|(_, data)| BitStorage::from_longs(Layout::Padded, bits, HEIGHTMAP_ENTRIES, data.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk() -> Tag {
+        let stone = BlockState::new("minecraft:stone").to_tag();
+        let mut data = BitStorage::new(Layout::Padded, MIN_BLOCK_BITS, SECTION_BLOCKS).unwrap();
+        for index in 0..256 {
+            data.set(index, 1);
+        }
+        Tag::compound(vec![
+            ("DataVersion", Tag::Int(3700)),
+            ("xPos", Tag::Int(-2)),
+            ("zPos", Tag::Int(3)),
+            ("yPos", Tag::Int(-4)),
+            ("Status", Tag::from("minecraft:full")),
+            ("LastUpdate", Tag::Long(1000)),
+            ("InhabitedTime", Tag::Long(20)),
+            ("sections", Tag::List(vec![
+                Tag::compound(vec![("Y", Tag::Byte(-5)), ("SkyLight", Tag::ByteArray(vec![0; 2048]))]),
+                Tag::compound(vec![
+                    ("Y", Tag::Byte(-4)),
+                    ("block_states", Tag::compound(vec![
+                        ("palette", Tag::List(vec![BlockState::air().to_tag(), stone])),
+                        ("data", Tag::LongArray(data.into_longs())),
+                    ])),
+                    ("biomes", Tag::compound(vec![("palette", Tag::List(vec![Tag::from("minecraft:desert")]))])),
+                ]),
+            ])),
+            ("block_entities", Tag::List(Vec::new())),
+            ("Heightmaps", Tag::compound(vec![("WORLD_SURFACE", Tag::LongArray(vec![0; 37]))])),
+            ("structures", Tag::compound(vec![("References", Tag::compound(Vec::<(String, Tag)>::new()))])),
+            ("isLightOn", Tag::Byte(1)),
+        ])
+    }
+
+    #[test]
+    fn test_chunk() {
+        let tag = chunk();
+        let mut chunk = Chunk::from_tag(&tag).unwrap();
+        assert_eq!(chunk.other, vec![(String::from("isLightOn"), Tag::Byte(1))]);
+        assert_eq!(Chunk::from_tag(&chunk.to_tag()).unwrap(), chunk);
+
+        // The lowest layer of the lowest section is stone
+        assert_eq!(chunk.block(-32, -64, 48).map(|state| state.name.as_str()), Some("minecraft:stone"));
+        assert_eq!(chunk.block(-17, -63, 63).map(|state| state.name.as_str()), Some("minecraft:air"));
+        assert_eq!(chunk.block(0, -64, 48), None);
+        assert_eq!(chunk.biome(-20, -60, 50), Some("minecraft:desert"));
+        let section = chunk.section(-4).unwrap();
+        assert!(section.block(15, 15, 15).is_some());
+        assert_eq!(section.block(0, 0, 16), None);
+        assert_eq!(section.block(200, 0, 0), None);
+        assert_eq!(section.biome(0, 16, 0), None);
+        assert!(chunk.heightmap("WORLD_SURFACE", 384).unwrap().is_ok());
+
+        // 16 distinct states need 4 bits, the 17th widens every index
+        for index in 0..17 {
+            chunk.set_block(-32 + index % 16, -60 + index / 16, 48, BlockState::new(format!("minecraft:block_{}", index))).unwrap();
+        }
+        chunk.set_block(-32, 100, 48, BlockState::new("minecraft:glass")).unwrap();
+        assert!(chunk.set_block(0, 0, 0, BlockState::air()).is_err());
+        // Sections are indexed by a Byte, section 252 must not wrap around to section -4
+        assert_eq!(chunk.block(-32, 252 * 16, 48), None);
+        match chunk.set_block(-32, 128 * 16, 48, BlockState::air()) {
+            Err(Error::OutOfBounds { y, .. }) => assert_eq!(y, 2048),
+            result => panic!("Expected Error::OutOfBounds, got {:?}", result),
+        }
+
+        let reread = Chunk::from_tag(&chunk.to_tag()).unwrap();
+        let section = reread.section(-4).unwrap();
+        assert_eq!(section.blocks.as_ref().unwrap().data.as_ref().unwrap().bits(), 5);
+        assert_eq!(reread.block(-31, -60, 48), Some(&BlockState::new("minecraft:block_1")));
+        assert_eq!(reread.block(-32, -59, 48), Some(&BlockState::new("minecraft:block_16")));
+        assert_eq!(reread.block(-32, 100, 48), Some(&BlockState::new("minecraft:glass")));
+        assert_eq!(reread.sections.last().map(|section| section.y), Some(6));
+
+        let mut legacy = tag;
+        legacy.insert("DataVersion", Tag::Int(2230));
+        assert!(Chunk::from_tag(&legacy).is_err());
+    }
+}
@@ -1,26 +1,176 @@
+use std::error;
+use std::fmt;
+use std::io;
+use std::result;
+use std::string::FromUtf8Error;
 
-error_chain! {
-    errors {
-        InvalidTagType(id: u8) {
-            description("Tag is not part of the NBT specification")
-            display("invalid tag type: '0x{:02X}'", id)
+use read::Limit;
+use read::TagPath;
+
+pub type Result<T> = result::Result<T, Error>;
+
+#[derive(Debug)]
+#[non_exhaustive]
+/// Every way reading or writing NBT data can fail
+pub enum Error {
+    /// The underlying reader or writer failed
+    Io(io::Error),
+    /// A String tag did not contain valid UTF-8
+    InvalidUtf8(FromUtf8Error),
+    /// Tag is not part of the NBT specification
+    InvalidTagType(u8),
+    /// The first byte of a file does not match any supported compression format
+    InvalidCompressionFormat(u8),
+    /// The file is in a format which is recognised, but cannot be read
+    UnsupportedFormat(&'static str),
+    /// A tag required by a format such as a structure file is missing
+    MissingTag(String),
+    /// A tag of a format such as a structure file has the wrong type or an invalid value
+    InvalidTag {
+        name: String,
+        expected: &'static str,
+    },
+    /// A block state could not be parsed from its string form
+    InvalidBlockState(String),
+    /// JSON could not be converted into a tag, see `json::from_json`
+    InvalidJson(String),
+    /// The name of a compression format could not be parsed, see `Compression::from_str`
+    UnknownCompression(String),
+    /// A tag of the wrong type was found within a container, such as a List
+    /// holding tags of different types
+    UnexpectedElement {
+        container: &'static str,
+        element_type: u8,
+    },
+    /// Coordinates lie outside of the chunk or other volume they were used with
+    OutOfBounds {
+        x: i32,
+        y: i32,
+        z: i32,
+    },
+    /// The length prefix of a List or array is negative
+    InvalidLength(i32),
+    /// The input ended before all bytes of a String could be read
+    TruncatedString {
+        expected: usize,
+        read: usize,
+    },
+    /// The input ended before all elements of an array could be read
+    TruncatedArray {
+        expected: usize,
+        read: usize,
+    },
+    /// The length given by a header does not match the data following it
+    LengthMismatch {
+        expected: u64,
+        read: u64,
+    },
+    /// Data follows the root tag of a file
+    TrailingData,
+    /// A String is longer than its unsigned 16 bit length prefix allows
+    StringTooLong(usize),
+    /// A List or array is longer than its signed 32 bit length prefix allows
+    CollectionTooLong(usize),
+    /// One of the `ReadOptions` limits was exceeded
+    LimitExceeded {
+        limit: Limit,
+        max: u64,
+    },
+    /// Wraps any error encountered while reading a tag with its location
+    Position {
+        offset: u64,
+        path: TagPath,
+        source: Box<Error>,
+    },
+}
+
+impl Error {
+    /// Attach the location of the tag which failed to be read
+    pub fn at(self, offset: u64, path: TagPath) -> Self {
+        Error::Position {
+            offset,
+            path,
+            source: Box::new(self),
         }
-        InvalidCompressionFormat(format_header: u8) {
-            description("invalid compression format"),
-            display("invalid compression format: '0x{:02X}'", format_header)
+    }
+
+    /// The error itself, skipping over any `Error::Position` wrapping it
+    pub fn inner(&self) -> &Error {
+        match *self {
+            Error::Position { ref source, .. } => source.inner(),
+            ref error => error,
         }
-        UnexpectedElement(container_type: String, element_type: u8) {
-            description("tags are heterogeneous")
-            display("invalid element in {}: '0x{:02X}'", container_type, element_type)
+    }
+
+    /// Byte offset of the tag which failed to be read, if known
+    pub fn offset(&self) -> Option<u64> {
+        match *self {
+            Error::Position { offset, .. } => Some(offset),
+            _ => None,
         }
-        InvalidHeaderLength(expected: i32) {
-            description("invalid header length"),
-            display("header length mismatch, expected length of {}", expected)
+    }
+
+    /// Path to the tag which failed to be read, if known
+    pub fn path(&self) -> Option<&TagPath> {
+        match *self {
+            Error::Position { ref path, .. } => Some(path),
+            _ => None,
         }
     }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Io(ref e) => write!(f, "io error: {}", e),
+            Error::InvalidUtf8(ref e) => write!(f, "invalid string: {}", e),
+            Error::InvalidTagType(id) => write!(f, "invalid tag type: '0x{:02X}'", id),
+            Error::InvalidCompressionFormat(header) => write!(f, "invalid compression format: '0x{:02X}'", header),
+            Error::UnsupportedFormat(format) => write!(f, "unsupported format: {}", format),
+            Error::MissingTag(ref name) => write!(f, "missing tag '{}'", name),
+            Error::InvalidTag { ref name, expected } => write!(f, "invalid tag '{}', expected {}", name, expected),
+            Error::InvalidBlockState(ref state) => write!(f, "invalid block state: '{}'", state),
+            Error::InvalidJson(ref message) => write!(f, "invalid JSON: {}", message),
+            Error::UnknownCompression(ref name) => write!(f, "unknown compression type: '{}'", name),
+            Error::UnexpectedElement { container, element_type } =>
+                write!(f, "invalid element in {}: '0x{:02X}'", container, element_type),
+            Error::OutOfBounds { x, y, z } => write!(f, "coordinates {}, {}, {} are out of bounds", x, y, z),
+            Error::InvalidLength(length) => write!(f, "invalid length: {}", length),
+            Error::TruncatedString { expected, read } =>
+                write!(f, "string truncated after {} of {} bytes", read, expected),
+            Error::TruncatedArray { expected, read } =>
+                write!(f, "array truncated after {} of {} elements", read, expected),
+            Error::LengthMismatch { expected, read } =>
+                write!(f, "header length mismatch, expected length of {} but read {}", expected, read),
+            Error::TrailingData => write!(f, "unexpected data after the root tag"),
+            Error::StringTooLong(length) => write!(f, "string of {} bytes is too long to be encoded", length),
+            Error::CollectionTooLong(length) => write!(f, "{} elements are too many to be encoded", length),
+            Error::LimitExceeded { limit, max } => write!(f, "{} limit of {} exceeded", limit, max),
+            Error::Position { offset, ref path, .. } =>
+                write!(f, "failed to read tag '{}' at byte offset {}", path, offset),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Error::Io(ref e) => Some(e),
+            Error::InvalidUtf8(ref e) => Some(e),
+            Error::Position { ref source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
 
-    foreign_links {
-        Io(::std::io::Error);
-        FromUtf8(::std::string::FromUtf8Error);
+impl From<FromUtf8Error> for Error {
+    fn from(e: FromUtf8Error) -> Self {
+        Error::InvalidUtf8(e)
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,98 @@
+//! Helpers for reading the entries of Compounds which follow a known format, turning
+//! missing entries and entries of the wrong type into errors naming the entry
+use errors::*;
+
+use bits::NibbleArray;
+use tag::Tag;
+
+pub fn invalid(name: &str, expected: &'static str) -> Error {
+    Error::InvalidTag { name: name.to_owned(), expected }
+}
+
+pub fn field<'a>(compound: &'a Tag, name: &str) -> Result<&'a Tag> {
+    compound.get(name).ok_or_else(|| Error::MissingTag(name.to_owned()))
+}
+
+pub fn int(compound: &Tag, name: &str) -> Result<i32> {
+    field(compound, name)?.as_i32().ok_or_else(|| invalid(name, "an Int"))
+}
+
+pub fn long(compound: &Tag, name: &str) -> Result<i64> {
+    field(compound, name)?.as_i64().ok_or_else(|| invalid(name, "a Long"))
+}
+
+pub fn float(compound: &Tag, name: &str) -> Result<f32> {
+    field(compound, name)?.as_f64().map(|value| value as f32).ok_or_else(|| invalid(name, "a Float"))
+}
+
+pub fn string<'a>(compound: &'a Tag, name: &str) -> Result<&'a str> {
+    field(compound, name)?.as_str().ok_or_else(|| invalid(name, "a String"))
+}
+
+pub fn list<'a>(compound: &'a Tag, name: &str) -> Result<&'a [Tag]> {
+    field(compound, name)?.as_list().ok_or_else(|| invalid(name, "a List"))
+}
+
+pub fn byte_array<'a>(compound: &'a Tag, name: &str) -> Result<&'a [i8]> {
+    field(compound, name)?.as_byte_array().ok_or_else(|| invalid(name, "a ByteArray"))
+}
+
+pub fn long_array<'a>(compound: &'a Tag, name: &str) -> Result<&'a [i64]> {
+    field(compound, name)?.as_long_array().ok_or_else(|| invalid(name, "a LongArray"))
+}
+
+/// A ByteArray of `len` nibbles, such as the light levels of a chunk section
+pub fn nibble_array(compound: &Tag, name: &str, len: usize) -> Result<NibbleArray> {
+    match byte_array(compound, name)? {
+        bytes if bytes.len() * 2 == len => Ok(NibbleArray::from_bytes(bytes.to_vec())),
+        _ => Err(invalid(name, "a nibble for every entry")),
+    }
+}
+
+/// Read an entry with `read` if it is present
+pub fn optional<'a, T, F>(compound: &'a Tag, name: &str, read: F) -> Result<Option<T>>
+    where F: FnOnce(&'a Tag, &str) -> Result<T>
+{
+    match compound.get(name) {
+        Some(..) => read(compound, name).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// Every entry of a Compound other than the `known` ones, so they survive a round trip
+pub fn others(compound: &Tag, known: &[&str]) -> Vec<(String, Tag)> {
+    compound.entries().into_iter().flatten()
+        .filter(|(name, _)| !known.contains(name))
+        .map(|(name, tag)| (name.to_owned(), tag.clone()))
+        .collect()
+}
+
+/// A position stored either as a List of three Ints or as an IntArray
+pub fn int_vec3(compound: &Tag, name: &str) -> Result<[i32; 3]> {
+    let values: Option<Vec<i32>> = match *field(compound, name)? {
+        Tag::List(ref elements) => elements.iter().map(Tag::as_i32).collect(),
+        Tag::IntArray(ref values) => Some(values.clone()),
+        _ => None,
+    };
+    match values {
+        Some(ref values) if values.len() == 3 => Ok([values[0], values[1], values[2]]),
+        _ => Err(invalid(name, "three Ints")),
+    }
+}
+
+/// A position stored as a List of three Doubles
+pub fn double_vec3(compound: &Tag, name: &str) -> Result<[f64; 3]> {
+    let values: Option<Vec<f64>> = list(compound, name)?.iter().map(Tag::as_f64).collect();
+    match values {
+        Some(ref values) if values.len() == 3 => Ok([values[0], values[1], values[2]]),
+        _ => Err(invalid(name, "three Doubles")),
+    }
+}
+
+pub fn int_list(values: [i32; 3]) -> Tag {
+    Tag::List(values.iter().map(|&value| Tag::Int(value)).collect())
+}
+
+pub fn double_list(values: [f64; 3]) -> Tag {
+    Tag::List(values.iter().map(|&value| Tag::Double(value)).collect())
+}
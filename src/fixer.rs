@@ -0,0 +1,423 @@
+//! Upgrades of data written by older versions of Minecraft, modelled on the game's own
+//! data fixers.
+//!
+//! Every `Fix` declares the data versions it applies to and transforms a Compound in
+//! place. A `Fixer` holds a set of fixes and applies those needed to bring a Compound
+//! from its `DataVersion` to a target version, oldest first. Data versions follow the
+//! schemas of Minecraft's data fixers, so a fix of schema 3692 applies to data older
+//! than 3692. The builtin fixes only handle data written since the flattening in 1.13.
+use std::mem;
+use std::ops::Range;
+use std::vec;
+use errors::*;
+
+use fields;
+use item::ItemStack;
+use legacy_chunk::FLATTENING_DATA_VERSION;
+use tag::Tag;
+
+/// Schema renaming the dirt path block and item, previously grass path
+pub const DIRT_PATH_DATA_VERSION: i32 = 2680;
+/// Schema renaming the grass block and item, which is not the grass block, to short grass
+pub const SHORT_GRASS_DATA_VERSION: i32 = 3692;
+/// Schema moving item data from `tag` to data components
+pub const ITEM_COMPONENTS_DATA_VERSION: i32 = 3818;
+
+pub trait Fix {
+    /// Name of the fix, as reported by `Fixer::upgrade`
+    fn name(&self) -> &str;
+
+    /// Data versions the fix applies to, data at `versions().end` or later no longer
+    /// needs it. Data older than `versions().start` has to be brought there by earlier
+    /// fixes, otherwise the upgrade fails.
+    fn versions(&self) -> Range<i32>;
+
+    /// Transform a Compound, such as a chunk or player, which `versions` applies to
+    fn apply(&self, tag: &mut Tag) -> Result<()>;
+}
+
+/// A fix applying a closure, see `fix_fn`
+pub struct FnFix<F> {
+    name: String,
+    versions: Range<i32>,
+    transform: F,
+}
+
+/// Create a fix from a closure
+pub fn fix_fn<S, F>(name: S, versions: Range<i32>, transform: F) -> FnFix<F>
+    where S: Into<String>, F: Fn(&mut Tag) -> Result<()>
+{
+    FnFix { name: name.into(), versions, transform }
+}
+
+impl<F: Fn(&mut Tag) -> Result<()>> Fix for FnFix<F> {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn versions(&self) -> Range<i32> {
+        self.versions.clone()
+    }
+
+    fn apply(&self, tag: &mut Tag) -> Result<()> {
+        (self.transform)(tag)
+    }
+}
+
+/// Whether a Compound looks like an item stack in either layout
+fn is_item(tag: &Tag) -> bool {
+    tag.get("id").and_then(Tag::as_str).is_some() && (tag.get("Count").is_some() || tag.get("count").is_some())
+}
+
+/// Whether a Compound looks like a block state, `{Name, Properties}`
+fn is_block_state(tag: &Tag) -> bool {
+    tag.get("Name").and_then(Tag::as_str).is_some()
+        && tag.entries().is_some_and(|mut entries| entries.all(|(name, _)| name == "Name" || name == "Properties"))
+}
+
+/// A List or Compound being walked by `visit_compounds`, its entries are moved out
+/// while they are walked and moved back once done
+enum Frame {
+    Compound {
+        done: Vec<(String, Tag)>,
+        name: String,
+        rest: vec::IntoIter<(String, Tag)>,
+    },
+    List {
+        done: Vec<Tag>,
+        rest: vec::IntoIter<Tag>,
+    },
+}
+
+impl Frame {
+    /// The next entry to walk
+    fn next(&mut self) -> Option<Tag> {
+        match *self {
+            Frame::Compound { ref mut name, ref mut rest, .. } => rest.next().map(|(next, tag)| {
+                *name = next;
+                tag
+            }),
+            Frame::List { ref mut rest, .. } => rest.next(),
+        }
+    }
+
+    /// Move back the entry last returned by `next`
+    fn finish(&mut self, tag: Tag) {
+        match *self {
+            Frame::Compound { ref mut done, ref mut name, .. } => done.push((mem::take(name), tag)),
+            Frame::List { ref mut done, .. } => done.push(tag),
+        }
+    }
+
+    fn into_tag(self) -> Tag {
+        match self {
+            Frame::Compound { mut done, rest, .. } => {
+                done.extend(rest);
+                Tag::Compound(done)
+            },
+            Frame::List { mut done, rest } => {
+                done.extend(rest);
+                Tag::List(done)
+            },
+        }
+    }
+}
+
+/// Push a frame for the entries of a List or Compound, other tags are returned as they are
+fn descend(tag: Tag, stack: &mut Vec<Frame>) -> Option<Tag> {
+    match tag {
+        Tag::Compound(entries) => {
+            let done = Vec::with_capacity(entries.len());
+            stack.push(Frame::Compound { done, name: String::new(), rest: entries.into_iter() });
+            None
+        },
+        Tag::List(elements) => {
+            stack.push(Frame::List { done: Vec::with_capacity(elements.len()), rest: elements.into_iter() });
+            None
+        },
+        tag => Some(tag),
+    }
+}
+
+/// Call `visit` with every Compound nested within `tag`, innermost first.
+///
+/// Like `NbtWriter::write_tag` this uses an explicit stack rather than recursion, so
+/// arbitrarily deep structures can be walked regardless of the call stack size. On
+/// error the entries are moved back, leaving any changes made so far.
+fn visit_compounds<F>(tag: &mut Tag, visit: &mut F) -> Result<()>
+    where F: FnMut(&mut Tag) -> Result<()>
+{
+    let mut stack = Vec::new();
+    let mut walked = descend(mem::replace(tag, Tag::End), &mut stack);
+    let mut result = Ok(());
+    while let Some(frame) = stack.last_mut() {
+        if let Some(child) = walked.take() {
+            frame.finish(child);
+        }
+        if let Some(child) = frame.next() {
+            walked = descend(child, &mut stack);
+            continue;
+        }
+
+        let mut finished = stack.pop().map(Frame::into_tag).expect("frame is on the stack");
+        if let Tag::Compound(..) = finished {
+            result = visit(&mut finished);
+        }
+        walked = Some(finished);
+        if result.is_err() {
+            break;
+        }
+    }
+
+    // Move every entry back into its parent, which is only left to do on error
+    let mut child = walked.expect("walk ended with a tag");
+    while let Some(mut frame) = stack.pop() {
+        frame.finish(child);
+        child = frame.into_tag();
+    }
+    *tag = child;
+    result
+}
+
+/// Renames the ids of item stacks and the names of block states found anywhere
+/// within the data
+pub struct RenameFix {
+    pub name: &'static str,
+    pub versions: Range<i32>,
+    /// Pairs of the old and new namespaced id
+    pub renames: &'static [(&'static str, &'static str)],
+}
+
+impl RenameFix {
+    fn renamed(&self, id: &str) -> Option<&'static str> {
+        self.renames.iter().find(|&&(old, _)| old == id).map(|&(_, new)| new)
+    }
+}
+
+impl Fix for RenameFix {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn versions(&self) -> Range<i32> {
+        self.versions.clone()
+    }
+
+    fn apply(&self, tag: &mut Tag) -> Result<()> {
+        visit_compounds(tag, &mut |compound| {
+            let key = if is_item(compound) { "id" } else if is_block_state(compound) { "Name" } else { return Ok(()) };
+            if let Some(new) = compound.get(key).and_then(Tag::as_str).and_then(|id| self.renamed(id)) {
+                compound.insert(key, Tag::from(new));
+            }
+            Ok(())
+        })
+    }
+}
+
+/// Moves item stacks written before 1.20.5 to data components, see `ItemStack::to_components`
+pub struct ItemComponentsFix;
+
+impl Fix for ItemComponentsFix {
+    fn name(&self) -> &str {
+        "item_components"
+    }
+
+    fn versions(&self) -> Range<i32> {
+        0..ITEM_COMPONENTS_DATA_VERSION
+    }
+
+    fn apply(&self, tag: &mut Tag) -> Result<()> {
+        visit_compounds(tag, &mut |compound| {
+            if is_item(compound) && compound.get("Count").is_some() {
+                *compound = ItemStack::from_tag(compound)?.to_components().to_tag();
+            }
+            Ok(())
+        })
+    }
+}
+
+#[derive(Default)]
+pub struct Fixer {
+    fixes: Vec<Box<dyn Fix>>,
+}
+
+impl Fixer {
+    /// Create a fixer without any fixes
+    pub fn new() -> Self {
+        Fixer::default()
+    }
+
+    /// Create a fixer holding the fixes of this module
+    pub fn builtin() -> Self {
+        let mut fixer = Fixer::new();
+        fixer.add(RenameFix {
+            name: "dirt_path",
+            versions: FLATTENING_DATA_VERSION..DIRT_PATH_DATA_VERSION,
+            renames: &[("minecraft:grass_path", "minecraft:dirt_path")],
+        });
+        fixer.add(RenameFix {
+            name: "short_grass",
+            versions: FLATTENING_DATA_VERSION..SHORT_GRASS_DATA_VERSION,
+            renames: &[("minecraft:grass", "minecraft:short_grass")],
+        });
+        fixer.add(ItemComponentsFix);
+        fixer
+    }
+
+    pub fn add<F: Fix + 'static>(&mut self, fix: F) -> &mut Self {
+        self.fixes.push(Box::new(fix));
+        self
+    }
+
+    /// Upgrade a Compound from its `DataVersion` to `target`, updating the version.
+    /// Returns the names of the fixes applied, in order.
+    pub fn upgrade(&self, tag: &mut Tag, target: i32) -> Result<Vec<&str>> {
+        let version = fields::int(tag, "DataVersion")?;
+        let applied = self.upgrade_from(tag, version, target)?;
+        tag.insert("DataVersion", Tag::Int(target));
+        Ok(applied)
+    }
+
+    /// Upgrade data written at `version` to `target`, for data without a `DataVersion`.
+    ///
+    /// Fails without changing the data when a fix is needed for data older than its
+    /// `versions().start`, and no fix applied before it brings the data that far.
+    pub fn upgrade_from(&self, tag: &mut Tag, version: i32, target: i32) -> Result<Vec<&str>> {
+        if target < version {
+            return Err(Error::UnsupportedFormat("data newer than the target version"));
+        }
+        let mut fixes: Vec<&dyn Fix> = self.fixes.iter()
+            .map(|fix| fix.as_ref())
+            .filter(|fix| version < fix.versions().end && fix.versions().end <= target)
+            .collect();
+        fixes.sort_by_key(|fix| fix.versions().end);
+
+        let mut reached = version;
+        for fix in &fixes {
+            if reached < fix.versions().start {
+                return Err(Error::UnsupportedFormat("data older than the fixes handle"));
+            }
+            reached = reached.max(fix.versions().end);
+        }
+
+        let mut applied = Vec::new();
+        for fix in fixes {
+            fix.apply(tag)?;
+            applied.push(fix.name());
+        }
+        Ok(applied)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    fn chunk(data_version: i32) -> Tag {
+        Tag::compound(vec![
+            ("DataVersion", Tag::Int(data_version)),
+            ("sections", Tag::List(vec![Tag::compound(vec![
+                ("block_states", Tag::compound(vec![("palette", Tag::List(vec![
+                    Tag::compound(vec![("Name", Tag::from("minecraft:grass_path"))]),
+                    Tag::compound(vec![("Name", Tag::from("minecraft:grass"))]),
+                ]))])),
+            ])])),
+            ("block_entities", Tag::List(vec![Tag::compound(vec![
+                ("id", Tag::from("minecraft:chest")),
+                ("Items", Tag::List(vec![Tag::compound(vec![
+                    ("Slot", Tag::Byte(0)),
+                    ("id", Tag::from("minecraft:grass")),
+                    ("Count", Tag::Byte(3)),
+                    ("tag", Tag::compound(vec![("Damage", Tag::Int(1))])),
+                ])])),
+            ])])),
+        ])
+    }
+
+    #[test]
+    fn test_fixer() {
+        let fixer = Fixer::builtin();
+        let mut tag = chunk(2586);
+        assert_eq!(fixer.upgrade(&mut tag, 3700).unwrap(), vec!["dirt_path", "short_grass"]);
+        assert_eq!(tag.get("DataVersion"), Some(&Tag::Int(3700)));
+        let palette = &tag.get("sections").and_then(Tag::as_list).unwrap()[0];
+        let palette = palette.get("block_states").and_then(|states| states.get("palette")).and_then(Tag::as_list).unwrap();
+        assert_eq!(palette[0].get("Name"), Some(&Tag::from("minecraft:dirt_path")));
+        assert_eq!(palette[1].get("Name"), Some(&Tag::from("minecraft:short_grass")));
+
+        let applied = fixer.upgrade(&mut tag, 3953).unwrap();
+        assert_eq!(applied, vec!["item_components"]);
+        let chest = &tag.get("block_entities").and_then(Tag::as_list).unwrap()[0];
+        let item = &chest.get("Items").and_then(Tag::as_list).unwrap()[0];
+        assert_eq!(item.get("id"), Some(&Tag::from("minecraft:short_grass")));
+        assert_eq!(item.get("count"), Some(&Tag::Int(3)));
+        assert_eq!(item.get("Slot"), Some(&Tag::Byte(0)));
+        assert_eq!(item.get("components").and_then(|components| components.get("minecraft:damage")), Some(&Tag::Int(1)));
+        assert!(fixer.upgrade(&mut tag, 3700).is_err());
+
+        // Before the flattening minecraft:grass is the grass block, which must not be renamed
+        let mut tag = chunk(1343);
+        let expected = tag.clone();
+        assert!(fixer.upgrade(&mut tag, 3700).is_err());
+        assert_eq!(tag, expected);
+        assert_eq!(fixer.upgrade(&mut tag, 1400).unwrap(), Vec::<&str>::new());
+
+        let mut custom = Fixer::new();
+        custom.add(fix_fn("mark", 0..100, |tag: &mut Tag| {
+            tag.insert("Marked", Tag::Byte(1));
+            Ok(())
+        }));
+        let mut tag = Tag::compound(vec![("Name", Tag::from("old"))]);
+        assert_eq!(custom.upgrade_from(&mut tag, 50, 99).unwrap(), Vec::<&str>::new());
+        assert_eq!(custom.upgrade_from(&mut tag, 50, 100).unwrap(), vec!["mark"]);
+        assert_eq!(tag.get("Marked"), Some(&Tag::Byte(1)));
+        assert!(custom.upgrade(&mut tag, 100).is_err());
+
+        // Fixes of later versions are applied too when the upgrade passes through them
+        let mut chained = Fixer::new();
+        chained.add(fix_fn("b", 100..200, |_: &mut Tag| Ok(())));
+        chained.add(fix_fn("a", 0..100, |_: &mut Tag| Ok(())));
+        let mut tag = Tag::compound(vec![("DataVersion", Tag::Int(50))]);
+        assert_eq!(chained.upgrade(&mut tag, 300).unwrap(), vec!["a", "b"]);
+        assert_eq!(chained.upgrade_from(&mut tag, 150, 300).unwrap(), vec!["b"]);
+        assert_eq!(chained.upgrade_from(&mut tag, 50, 150).unwrap(), vec!["a"]);
+        let mut gap = Fixer::new();
+        gap.add(fix_fn("c", 100..200, |_: &mut Tag| Ok(())));
+        assert!(gap.upgrade_from(&mut tag, 50, 300).is_err());
+        assert_eq!(gap.upgrade_from(&mut tag, 100, 300).unwrap(), vec!["c"]);
+    }
+
+    #[test]
+    fn test_deep_nesting() {
+        const DEPTH: usize = 500;
+        let mut tag = Tag::compound(vec![("Name", Tag::from("minecraft:grass_path"))]);
+        for _ in 1..DEPTH {
+            tag = Tag::List(vec![Tag::compound(vec![("nested", tag)])]);
+        }
+
+        // Like reading and writing, fixes must not use the call stack for nesting
+        let fixed = thread::Builder::new().stack_size(64 * 1024).spawn(move || {
+            let applied = Fixer::builtin().upgrade_from(&mut tag, 2586, 2680).map(|applied| applied.len());
+            (tag, applied)
+        }).unwrap().join().unwrap();
+        let (mut tag, applied) = fixed;
+        assert_eq!(applied.unwrap(), 1);
+        let mut innermost = &tag;
+        while let Some(nested) = innermost.as_list().and_then(|list| list[0].get("nested")) {
+            innermost = nested;
+        }
+        assert_eq!(innermost.get("Name"), Some(&Tag::from("minecraft:dirt_path")));
+
+        // On error the structure is left in place
+        let expected = tag.clone();
+        let failing = fix_fn("fail", 0..100, |tag: &mut Tag| visit_compounds(tag, &mut |compound| {
+            match compound.get("Name") {
+                Some(..) => Err(Error::MissingTag(String::from("Properties"))),
+                None => Ok(()),
+            }
+        }));
+        assert!(failing.apply(&mut tag).is_err());
+        assert_eq!(tag, expected);
+    }
+}
@@ -0,0 +1,451 @@
+//! Item stacks and the inventories holding them.
+//!
+//! Before 1.20.5 items were stored as `{id, Count, tag}` with a Byte count and any
+//! further data in a free-form `tag` Compound. Since then they are stored as
+//! `{id, count, components}` with an Int count and typed data components.
+//!
+//! `ItemStack::to_components` and `ItemStack::to_legacy` convert between the two for
+//! the common components. As Minecraft does, anything in `tag` without a component of
+//! its own is kept in `minecraft:custom_data`.
+use std::convert::TryFrom;
+use errors::*;
+
+use fields;
+use tag::Tag;
+use uuid::Uuid;
+
+const CUSTOM_NAME: &str = "minecraft:custom_name";
+const LORE: &str = "minecraft:lore";
+const DAMAGE: &str = "minecraft:damage";
+const ENCHANTMENTS: &str = "minecraft:enchantments";
+const STORED_ENCHANTMENTS: &str = "minecraft:stored_enchantments";
+const ATTRIBUTE_MODIFIERS: &str = "minecraft:attribute_modifiers";
+const CUSTOM_DATA: &str = "minecraft:custom_data";
+
+/// Legacy names of the enchantment lists and the components replacing them
+const ENCHANTMENT_LISTS: &[(&str, &str)] = &[
+    ("Enchantments", ENCHANTMENTS),
+    ("StoredEnchantments", STORED_ENCHANTMENTS),
+];
+/// Attribute modifier operations, by their legacy number
+const OPERATIONS: &[&str] = &["add_value", "add_multiplied_base", "add_multiplied_total"];
+
+/// Entries of an item stack which are read and written by `ItemStack` itself
+const KNOWN: &[&str] = &["id", "Count", "count", "tag", "components", "Slot"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ItemStack {
+    /// Such as `minecraft:diamond_sword`
+    pub id: String,
+    pub count: i32,
+    /// Slot within the inventory holding the item, if any
+    pub slot: Option<i8>,
+    pub data: ItemData,
+    /// Every other entry of the item
+    pub other: Vec<(String, Tag)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// Data of an item beyond its id and count, in either layout
+pub enum ItemData {
+    /// The `tag` Compound written before 1.20.5, if present
+    Tag(Option<Tag>),
+    /// The data components written since 1.20.5, by their namespaced id
+    Components(Vec<(String, Tag)>),
+}
+
+impl ItemStack {
+    /// Create a stack using the layout since 1.20.5
+    pub fn new<S: Into<String>>(id: S, count: i32) -> Self {
+        ItemStack {
+            id: id.into(),
+            count,
+            slot: None,
+            data: ItemData::Components(Vec::new()),
+            other: Vec::new(),
+        }
+    }
+
+    /// Read an item in either layout, which is told apart by the case of its count
+    pub fn from_tag(tag: &Tag) -> Result<Self> {
+        let data = if tag.get("Count").is_some() {
+            ItemData::Tag(tag.get("tag").cloned())
+        } else {
+            let components = match tag.get("components") {
+                Some(components) => components.entries()
+                    .ok_or_else(|| fields::invalid("components", "a Compound"))?
+                    .map(|(name, value)| (name.to_owned(), value.clone()))
+                    .collect(),
+                None => Vec::new(),
+            };
+            ItemData::Components(components)
+        };
+        let count = match data {
+            ItemData::Tag(..) => fields::int(tag, "Count")?,
+            ItemData::Components(..) => fields::optional(tag, "count", fields::int)?.unwrap_or(1),
+        };
+        Ok(ItemStack {
+            id: fields::string(tag, "id")?.to_owned(),
+            count,
+            slot: match fields::optional(tag, "Slot", fields::int)? {
+                Some(slot) => Some(i8::try_from(slot).map_err(|_| fields::invalid("Slot", "a Byte"))?),
+                None => None,
+            },
+            data,
+            other: fields::others(tag, KNOWN),
+        })
+    }
+
+    pub fn to_tag(&self) -> Tag {
+        // Inventories write the slot before the item itself
+        let mut tag = Tag::compound(Vec::<(String, Tag)>::new());
+        if let Some(slot) = self.slot {
+            tag.insert("Slot", Tag::Byte(slot));
+        }
+        tag.insert("id", Tag::from(self.id.as_str()));
+        match self.data {
+            ItemData::Tag(ref data) => {
+                // Counts which do not fit are kept as they are rather than wrapped around,
+                // see `to_legacy` for converting with a check
+                tag.insert("Count", match i8::try_from(self.count) {
+                    Ok(count) => Tag::Byte(count),
+                    Err(..) => Tag::Int(self.count),
+                });
+                if let Some(ref data) = *data {
+                    tag.insert("tag", data.clone());
+                }
+            },
+            ItemData::Components(ref components) => {
+                tag.insert("count", Tag::Int(self.count));
+                if !components.is_empty() {
+                    tag.insert("components", Tag::compound(components.iter()
+                        .map(|(name, value)| (name.as_str(), value.clone()))));
+                }
+            },
+        }
+        for (name, other) in &self.other {
+            tag.insert(name.as_str(), other.clone());
+        }
+        tag
+    }
+
+    /// A data component by its namespaced id, for items using components
+    pub fn component(&self, id: &str) -> Option<&Tag> {
+        match self.data {
+            ItemData::Components(ref components) => components.iter()
+                .find(|(name, _)| name == id)
+                .map(|(_, value)| value),
+            ItemData::Tag(..) => None,
+        }
+    }
+}
+
+fn is_empty_compound(tag: &Tag) -> bool {
+    tag.entries().is_some_and(|mut entries| entries.next().is_none())
+}
+
+/// Convert a legacy enchantment list, unless it holds numeric ids from before 1.13
+fn enchantments_to_component(list: &Tag) -> Option<Tag> {
+    let levels = list.as_list()?.iter()
+        .map(|enchantment| Some((enchantment.get("id")?.as_str()?, Tag::Int(enchantment.get("lvl")?.as_i32()?))))
+        .collect::<Option<Vec<_>>>()?;
+    Some(Tag::compound(vec![("levels", Tag::compound(levels))]))
+}
+
+fn enchantments_from_component(id: &str, component: &Tag) -> Result<Tag> {
+    let levels = component.get("levels").and_then(Tag::entries)
+        .ok_or_else(|| fields::invalid(id, "a Compound of levels"))?;
+    let enchantments = levels
+        .map(|(enchantment, level)| match level.as_i32().map(i16::try_from) {
+            Some(Ok(level)) => Ok(Tag::compound(vec![("id", Tag::from(enchantment)), ("lvl", Tag::Short(level))])),
+            Some(Err(..)) => Err(fields::invalid(id, "levels which fit into a Short")),
+            None => Err(fields::invalid(id, "Int levels")),
+        })
+        .collect::<Result<_>>()?;
+    Ok(Tag::List(enchantments))
+}
+
+fn modifier_to_component(modifier: &Tag) -> Option<Tag> {
+    let attribute = modifier.get("AttributeName")?.as_str()?;
+    let attribute = if attribute.contains(':') { attribute.to_owned() } else { format!("minecraft:{}", attribute) };
+    let operation = match modifier.get("Operation")?.as_i32()? {
+        operation if operation >= 0 => OPERATIONS.get(operation as usize)?,
+        _ => return None,
+    };
+    let mut component = Tag::compound(vec![
+        ("type", Tag::from(attribute)),
+        ("uuid", Uuid::from_compound(modifier, "UUID")?.to_tag()),
+        ("name", Tag::from(modifier.get("Name")?.as_str()?)),
+        ("amount", Tag::Double(modifier.get("Amount")?.as_f64()?)),
+        ("operation", Tag::from(*operation)),
+    ]);
+    if let Some(slot) = modifier.get("Slot") {
+        component.insert("slot", Tag::from(slot.as_str()?));
+    }
+    Some(component)
+}
+
+fn modifier_from_component(modifier: &Tag) -> Result<Tag> {
+    let invalid = || fields::invalid(ATTRIBUTE_MODIFIERS, "modifiers with a type, uuid, name, amount and operation");
+    let operation = modifier.get("operation").and_then(Tag::as_str)
+        .and_then(|operation| OPERATIONS.iter().position(|&known| known == operation))
+        .ok_or_else(invalid)?;
+    let mut tag = Tag::compound(vec![
+        ("AttributeName", Tag::from(modifier.get("type").and_then(Tag::as_str).ok_or_else(invalid)?)),
+        ("Name", Tag::from(modifier.get("name").and_then(Tag::as_str).ok_or_else(invalid)?)),
+        ("Amount", Tag::Double(modifier.get("amount").and_then(Tag::as_f64).ok_or_else(invalid)?)),
+        ("Operation", Tag::Int(operation as i32)),
+        ("UUID", Uuid::from_compound(modifier, "uuid").ok_or_else(invalid)?.to_tag()),
+    ]);
+    match modifier.get("slot").and_then(Tag::as_str) {
+        Some("any") | None => {},
+        Some(slot) => { tag.insert("Slot", Tag::from(slot)); },
+    }
+    Ok(tag)
+}
+
+impl ItemStack {
+    /// Convert an item to the layout used since 1.20.5, items already using it are
+    /// returned as they are
+    pub fn to_components(&self) -> ItemStack {
+        let tag = match self.data {
+            ItemData::Tag(ref tag) => tag,
+            ItemData::Components(..) => return self.clone(),
+        };
+        let mut custom = tag.clone().unwrap_or_else(|| Tag::compound(Vec::<(String, Tag)>::new()));
+        let mut components = Vec::new();
+
+        if let Some(display) = custom.get_mut("display") {
+            if let Some(name) = display.remove("Name") {
+                components.push((CUSTOM_NAME.to_owned(), name));
+            }
+            if let Some(lore) = display.remove("Lore") {
+                components.push((LORE.to_owned(), lore));
+            }
+        }
+        if custom.get("display").is_some_and(is_empty_compound) {
+            custom.remove("display");
+        }
+        if let Some(damage) = custom.get("Damage").and_then(Tag::as_i32) {
+            custom.remove("Damage");
+            components.push((DAMAGE.to_owned(), Tag::Int(damage)));
+        }
+        for &(name, id) in ENCHANTMENT_LISTS {
+            if let Some(enchantments) = custom.get(name).and_then(enchantments_to_component) {
+                custom.remove(name);
+                components.push((id.to_owned(), enchantments));
+            }
+        }
+        let modifiers = custom.get("AttributeModifiers").and_then(Tag::as_list)
+            .and_then(|modifiers| modifiers.iter().map(modifier_to_component).collect::<Option<Vec<_>>>());
+        if let Some(modifiers) = modifiers {
+            custom.remove("AttributeModifiers");
+            components.push((ATTRIBUTE_MODIFIERS.to_owned(), Tag::compound(vec![("modifiers", Tag::List(modifiers))])));
+        }
+        if !is_empty_compound(&custom) {
+            components.push((CUSTOM_DATA.to_owned(), custom));
+        }
+
+        ItemStack { data: ItemData::Components(components), ..self.clone() }
+    }
+
+    /// Convert an item to the layout used before 1.20.5, failing for components which
+    /// have no counterpart in it and for counts which do not fit into its Byte. Items
+    /// already using it are returned as they are.
+    pub fn to_legacy(&self) -> Result<ItemStack> {
+        let components = match self.data {
+            ItemData::Components(ref components) => components,
+            ItemData::Tag(..) => return Ok(self.clone()),
+        };
+        if i8::try_from(self.count).is_err() {
+            return Err(fields::invalid("count", "a count which fits into a Byte"));
+        }
+        let mut tag = match self.component(CUSTOM_DATA) {
+            Some(custom @ &Tag::Compound(..)) => custom.clone(),
+            Some(..) => return Err(fields::invalid(CUSTOM_DATA, "a Compound")),
+            None => Tag::compound(Vec::<(String, Tag)>::new()),
+        };
+        let mut display = tag.get("display").cloned().unwrap_or_else(|| Tag::compound(Vec::<(String, Tag)>::new()));
+
+        for (id, component) in components {
+            match id.as_str() {
+                CUSTOM_DATA => {},
+                CUSTOM_NAME => { display.insert("Name", component.clone()); },
+                LORE => { display.insert("Lore", component.clone()); },
+                DAMAGE => {
+                    let damage = component.as_i32().ok_or_else(|| fields::invalid(DAMAGE, "an Int"))?;
+                    tag.insert("Damage", Tag::Int(damage));
+                },
+                ENCHANTMENTS => { tag.insert("Enchantments", enchantments_from_component(id, component)?); },
+                STORED_ENCHANTMENTS => { tag.insert("StoredEnchantments", enchantments_from_component(id, component)?); },
+                ATTRIBUTE_MODIFIERS => {
+                    let modifiers = component.get("modifiers").and_then(Tag::as_list)
+                        .ok_or_else(|| fields::invalid(ATTRIBUTE_MODIFIERS, "a List of modifiers"))?;
+                    let modifiers = modifiers.iter().map(modifier_from_component).collect::<Result<_>>()?;
+                    tag.insert("AttributeModifiers", Tag::List(modifiers));
+                },
+                id => return Err(fields::invalid(id, "a component with a counterpart before 1.20.5")),
+            }
+        }
+        if !is_empty_compound(&display) {
+            tag.insert("display", display);
+        }
+
+        let tag = if is_empty_compound(&tag) { None } else { Some(tag) };
+        Ok(ItemStack { data: ItemData::Tag(tag), ..self.clone() })
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+/// The items of an inventory, each knowing its slot
+pub struct Inventory {
+    pub items: Vec<ItemStack>,
+}
+
+impl Inventory {
+    /// Read a List of items, each holding a `Slot`
+    pub fn from_tag(tag: &Tag) -> Result<Self> {
+        let items = tag.as_list()
+            .ok_or_else(|| fields::invalid("Inventory", "a List"))?
+            .iter()
+            .map(ItemStack::from_tag)
+            .collect::<Result<_>>()?;
+        Ok(Inventory { items })
+    }
+
+    pub fn to_tag(&self) -> Tag {
+        Tag::List(self.items.iter().map(ItemStack::to_tag).collect())
+    }
+
+    pub fn get(&self, slot: i8) -> Option<&ItemStack> {
+        self.items.iter().find(|item| item.slot == Some(slot))
+    }
+
+    pub fn get_mut(&mut self, slot: i8) -> Option<&mut ItemStack> {
+        self.items.iter_mut().find(|item| item.slot == Some(slot))
+    }
+
+    /// Put an item into a slot, returning the item it replaces
+    pub fn set(&mut self, slot: i8, mut item: ItemStack) -> Option<ItemStack> {
+        let previous = self.remove(slot);
+        item.slot = Some(slot);
+        self.items.push(item);
+        self.items.sort_by_key(|item| item.slot);
+        previous
+    }
+
+    pub fn remove(&mut self, slot: i8) -> Option<ItemStack> {
+        let index = self.items.iter().position(|item| item.slot == Some(slot))?;
+        Some(self.items.remove(index))
+    }
+
+    /// The first of the `slots` which holds no item
+    pub fn first_empty<I: IntoIterator<Item = i8>>(&self, slots: I) -> Option<i8> {
+        slots.into_iter().find(|&slot| self.get(slot).is_none())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_item_stack() {
+        let legacy = Tag::compound(vec![
+            ("Slot", Tag::Byte(3)),
+            ("id", Tag::from("minecraft:diamond_sword")),
+            ("Count", Tag::Byte(1)),
+            ("tag", Tag::compound(vec![("Damage", Tag::Int(5))])),
+        ]);
+        let item = ItemStack::from_tag(&legacy).unwrap();
+        assert_eq!(item.data, ItemData::Tag(legacy.get("tag").cloned()));
+        assert_eq!(item.slot, Some(3));
+        assert_eq!(item.to_tag(), legacy);
+
+        let current = Tag::compound(vec![
+            ("id", Tag::from("minecraft:diamond_sword")),
+            ("count", Tag::Int(1)),
+            ("components", Tag::compound(vec![("minecraft:damage", Tag::Int(5))])),
+        ]);
+        let item = ItemStack::from_tag(&current).unwrap();
+        assert_eq!(item.component("minecraft:damage"), Some(&Tag::Int(5)));
+        assert_eq!(item.to_tag(), current);
+
+        let mut inventory = Inventory::from_tag(&Tag::List(vec![legacy])).unwrap();
+        assert_eq!(inventory.first_empty(0..9), Some(0));
+        assert!(inventory.set(0, ItemStack::new("minecraft:stone", 64)).is_none());
+        assert_eq!(inventory.first_empty(0..9), Some(1));
+        let previous = inventory.set(3, item).unwrap();
+        assert_eq!(previous.id, "minecraft:diamond_sword");
+        assert_eq!(inventory.get(3).map(|item| item.slot), Some(Some(3)));
+        assert_eq!(Inventory::from_tag(&inventory.to_tag()).unwrap(), inventory);
+        assert!(inventory.remove(0).is_some());
+        assert!(inventory.get(0).is_none());
+    }
+
+    #[test]
+    fn test_component_migration() {
+        let uuid = Tag::IntArray(vec![1, 2, 3, 4]);
+        let legacy = ItemStack::from_tag(&Tag::compound(vec![
+            ("id", Tag::from("minecraft:diamond_sword")),
+            ("Count", Tag::Byte(1)),
+            ("tag", Tag::compound(vec![
+                ("display", Tag::compound(vec![
+                    ("color", Tag::Int(0xff0000)),
+                    ("Name", Tag::from(r#"{"text":"Excalibur"}"#)),
+                    ("Lore", Tag::List(vec![Tag::from(r#""Legendary""#)])),
+                ])),
+                ("PublicBukkitValues", Tag::compound(vec![("shop:price", Tag::Int(100))])),
+                ("Damage", Tag::Int(12)),
+                ("Enchantments", Tag::List(vec![Tag::compound(vec![
+                    ("id", Tag::from("minecraft:sharpness")),
+                    ("lvl", Tag::Short(5)),
+                ])])),
+                ("AttributeModifiers", Tag::List(vec![Tag::compound(vec![
+                    ("AttributeName", Tag::from("minecraft:generic.attack_damage")),
+                    ("Name", Tag::from("Weapon modifier")),
+                    ("Amount", Tag::Double(7.0)),
+                    ("Operation", Tag::Int(0)),
+                    ("UUID", uuid.clone()),
+                    ("Slot", Tag::from("mainhand")),
+                ])])),
+            ])),
+        ])).unwrap();
+
+        let current = legacy.to_components();
+        assert_eq!(current.component(CUSTOM_NAME), Some(&Tag::from(r#"{"text":"Excalibur"}"#)));
+        assert_eq!(current.component(DAMAGE), Some(&Tag::Int(12)));
+        assert_eq!(current.component(ENCHANTMENTS).and_then(|enchantments| enchantments.get("levels")),
+            Some(&Tag::compound(vec![("minecraft:sharpness", Tag::Int(5))])));
+        let modifiers = current.component(ATTRIBUTE_MODIFIERS).and_then(|modifiers| modifiers.get("modifiers"));
+        let modifier = &modifiers.and_then(Tag::as_list).unwrap()[0];
+        assert_eq!(modifier.get("operation"), Some(&Tag::from("add_value")));
+        assert_eq!(modifier.get("uuid"), Some(&uuid));
+        let custom = current.component(CUSTOM_DATA).unwrap();
+        assert_eq!(custom.get("display"), Some(&Tag::compound(vec![("color", Tag::Int(0xff0000))])));
+        assert!(custom.get("PublicBukkitValues").is_some());
+        assert_eq!(current.to_tag().get("count"), Some(&Tag::Int(1)));
+
+        assert_eq!(current.to_legacy().unwrap(), legacy);
+        assert_eq!(current.to_components(), current);
+
+        let mut food = ItemStack::new("minecraft:apple", 3);
+        food.data = ItemData::Components(vec![(String::from("minecraft:food"), Tag::compound(Vec::<(String, Tag)>::new()))]);
+        assert!(food.to_legacy().is_err());
+        let plain = ItemStack::new("minecraft:stone", 64).to_legacy().unwrap();
+        assert_eq!(plain.to_tag(), Tag::compound(vec![("id", Tag::from("minecraft:stone")), ("Count", Tag::Byte(64))]));
+
+        // A count of 200 must not be written as a Byte of -56
+        let stack = ItemStack::new("minecraft:stone", 200);
+        assert!(stack.to_legacy().is_err());
+        let forced = ItemStack { data: ItemData::Tag(None), ..stack };
+        assert_eq!(forced.to_tag().get("Count"), Some(&Tag::Int(200)));
+        assert_eq!(ItemStack::from_tag(&forced.to_tag()).unwrap(), forced);
+
+        // Neither must a level of 40000 be written as a Short of -25536
+        let mut enchanted = current;
+        let levels = Tag::compound(vec![("levels", Tag::compound(vec![("minecraft:sharpness", Tag::Int(40000))]))]);
+        enchanted.data = ItemData::Components(vec![(String::from(ENCHANTMENTS), levels)]);
+        assert!(enchanted.to_legacy().is_err());
+    }
+}
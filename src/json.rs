@@ -0,0 +1,418 @@
+//! Conversion between tags and JSON.
+//!
+//! In `JsonMode::Plain` numbers and strings are written as they are, which is easy to
+//! consume but loses the exact tag types. `JsonMode::Typed` wraps every tag in an object
+//! holding its type name and value, for example `{"type": "Short", "value": 3}`, so
+//! the JSON can be converted back into an identical tree of tags. The value of a
+//! Compound is an array of `[name, tag]` pairs, keeping the order of its entries,
+//! entries sharing a name and the End tag terminating it.
+use std::vec;
+use serde_json::Map;
+use serde_json::Number;
+use serde_json::Value;
+use errors::*;
+
+use tag::Tag;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum JsonMode {
+    /// Numbers, strings, arrays and objects without any type information
+    Plain,
+    /// Every tag is annotated with its type, which makes the conversion lossless
+    Typed,
+}
+
+/// Convert a tag into JSON
+pub fn to_json(tag: &Tag, mode: JsonMode) -> Value {
+    match mode {
+        JsonMode::Plain => to_plain(tag),
+        JsonMode::Typed => to_typed(tag),
+    }
+}
+
+/// Convert JSON back into a tag.
+///
+/// Typed JSON must have been produced by `to_json`. For plain JSON the tags are
+/// inferred from the values, see `from_plain`.
+pub fn from_json(value: &Value, mode: JsonMode) -> Result<Tag> {
+    match mode {
+        JsonMode::Plain => from_plain(value),
+        JsonMode::Typed => from_typed(value),
+    }
+}
+
+fn float(value: f64) -> Value {
+    Number::from_f64(value).map_or(Value::Null, Value::Number)
+}
+
+fn to_plain(tag: &Tag) -> Value {
+    let converted: Result<Value> = convert(tag, |tag| Ok(match *tag {
+        Tag::List(ref elements) => Node::Branch(elements.iter().map(|element| ("", element)).collect()),
+        Tag::Compound(ref entries) => Node::Branch(entries.iter()
+            .filter(|(_, tag)| *tag != Tag::End)
+            .map(|(name, tag)| (name.as_str(), tag))
+            .collect()),
+        ref tag => Node::Leaf(plain_scalar(tag)),
+    }), |tag, children| Ok(match *tag {
+        Tag::Compound(..) => Value::Object(children.into_iter().map(|(name, value)| (name.to_owned(), value)).collect()),
+        _ => Value::Array(children.into_iter().map(|(_, value)| value).collect()),
+    }));
+    converted.expect("converting a tag into plain JSON cannot fail")
+}
+
+/// A tag other than a List or Compound as plain JSON
+fn plain_scalar(tag: &Tag) -> Value {
+    match *tag {
+        Tag::Byte(value) => Value::from(value),
+        Tag::Short(value) => Value::from(value),
+        Tag::Int(value) => Value::from(value),
+        Tag::Long(value) => Value::from(value),
+        Tag::Float(value) => float(f64::from(value)),
+        Tag::Double(value) => float(value),
+        Tag::ByteArray(ref values) => Value::from(values.clone()),
+        Tag::String(ref value) => Value::from(value.as_str()),
+        Tag::IntArray(ref values) => Value::from(values.clone()),
+        Tag::LongArray(ref values) => Value::from(values.clone()),
+        Tag::End | Tag::List(..) | Tag::Compound(..) => Value::Null,
+    }
+}
+
+/// Floats which JSON cannot represent are written as strings
+fn special_float(value: f64) -> Value {
+    match value {
+        _ if value.is_nan() => Value::from("NaN"),
+        _ if value == f64::INFINITY => Value::from("Infinity"),
+        _ if value == f64::NEG_INFINITY => Value::from("-Infinity"),
+        _ => float(value),
+    }
+}
+
+fn to_typed(tag: &Tag) -> Value {
+    let converted: Result<Value> = convert(tag, |tag| Ok(match *tag {
+        Tag::End => Node::Leaf(typed(tag, None)),
+        Tag::Float(value) => Node::Leaf(typed(tag, Some(special_float(f64::from(value))))),
+        Tag::Double(value) => Node::Leaf(typed(tag, Some(special_float(value)))),
+        Tag::List(ref elements) => Node::Branch(elements.iter().map(|element| ("", element)).collect()),
+        Tag::Compound(ref entries) => Node::Branch(entries.iter().map(|(name, tag)| (name.as_str(), tag)).collect()),
+        ref tag => Node::Leaf(typed(tag, Some(plain_scalar(tag)))),
+    }), |tag, children| Ok(typed(tag, Some(match *tag {
+        Tag::Compound(..) => Value::Array(children.into_iter()
+            .map(|(name, value)| Value::Array(vec![Value::from(name), value]))
+            .collect()),
+        _ => Value::Array(children.into_iter().map(|(_, value)| value).collect()),
+    }))));
+    converted.expect("converting a tag into typed JSON cannot fail")
+}
+
+/// Annotate the JSON of a tag with its type
+fn typed(tag: &Tag, value: Option<Value>) -> Value {
+    let mut object = Map::new();
+    object.insert(String::from("type"), Value::from(tag.type_name()));
+    if let Some(value) = value {
+        object.insert(String::from("value"), value);
+    }
+    Value::Object(object)
+}
+
+fn invalid(message: &str, value: &Value) -> Error {
+    Error::InvalidJson(format!("{}, got {}", message, value))
+}
+
+/// Infer a tag from plain JSON:
+///
+/// * `true` and `false` become Bytes, as used by Minecraft for booleans
+/// * integers become Ints, or Longs when they do not fit, other numbers become Doubles
+/// * arrays of integers become IntArrays, or LongArrays when they do not fit
+/// * other arrays become Lists, elements of differing types are wrapped in Compounds
+///   under an empty name, as done by Minecraft for heterogeneous lists, see `wrap_elements`
+/// * objects become Compounds, `null` values are left out
+pub fn from_plain(value: &Value) -> Result<Tag> {
+    convert(value, |value| Ok(match *value {
+        Value::Null => return Err(invalid("expected a value", value)),
+        Value::Bool(value) => Node::Leaf(Tag::Byte(i8::from(value))),
+        Value::Number(ref number) => Node::Leaf(number_tag(number)),
+        Value::String(ref value) => Node::Leaf(Tag::String(value.clone())),
+        Value::Array(ref values) => {
+            let integers: Option<Vec<i64>> = values.iter().map(Value::as_i64).collect();
+            match integers {
+                Some(ref integers) if !integers.is_empty() => {
+                    if integers.iter().all(|&value| value as i32 as i64 == value) {
+                        Node::Leaf(Tag::IntArray(integers.iter().map(|&value| value as i32).collect()))
+                    } else {
+                        Node::Leaf(Tag::LongArray(integers.clone()))
+                    }
+                },
+                _ => Node::Branch(values.iter().map(|value| ("", value)).collect()),
+            }
+        },
+        Value::Object(ref object) => Node::Branch(object.iter()
+            .filter(|(_, value)| !value.is_null())
+            .map(|(name, value)| (name.as_str(), value))
+            .collect()),
+    }), |value, children| Ok(match *value {
+        Value::Object(..) => {
+            let mut entries: Vec<(String, Tag)> = children.into_iter().map(|(name, tag)| (name.to_owned(), tag)).collect();
+            entries.push((String::new(), Tag::End));
+            Tag::Compound(entries)
+        },
+        _ => wrap_elements(children.into_iter().map(|(_, tag)| tag).collect()),
+    }))
+}
+
+/// Integers become Ints, or Longs when they do not fit, other numbers become Doubles.
+/// Shared with `text`.
+pub(crate) fn number_tag(number: &Number) -> Tag {
+    match number.as_i64() {
+        Some(value) if value as i32 as i64 == value => Tag::Int(value as i32),
+        Some(value) => Tag::Long(value),
+        None => Tag::Double(number.as_f64().unwrap_or(f64::NAN)),
+    }
+}
+
+/// Build a List, wrapping the elements in Compounds under an empty name when their
+/// types differ or an element would otherwise be mistaken for a wrapper, as Minecraft
+/// does. Shared with `text`.
+pub(crate) fn wrap_elements(elements: Vec<Tag>) -> Tag {
+    let element_type = elements.first().map_or(0x00, Tag::type_id);
+    let mistaken = |element: &Tag| match *element {
+        Tag::Compound(ref entries) => wrapped(entries).is_some(),
+        _ => false,
+    };
+    if elements.iter().all(|element| element.type_id() == element_type && !mistaken(element)) {
+        return Tag::List(elements);
+    }
+    Tag::List(elements.into_iter()
+        .map(|element| match element {
+            // Compounds which would be mistaken for a wrapper must be wrapped themselves
+            Tag::Compound(ref entries) if wrapped(entries).is_none() => element,
+            element => Tag::Compound(vec![(String::new(), element), (String::new(), Tag::End)]),
+        })
+        .collect())
+}
+
+/// The element held by a Compound wrapping a List element, see `wrap_elements`
+pub(crate) fn wrapped(entries: &[(String, Tag)]) -> Option<&Tag> {
+    let mut entries = entries.iter().filter(|(_, tag)| *tag != Tag::End);
+    match (entries.next(), entries.next()) {
+        (Some((name, tag)), None) if name.is_empty() => Some(tag),
+        _ => None,
+    }
+}
+
+fn float_value(value: &Value) -> Result<f64> {
+    match *value {
+        Value::Number(ref number) => number.as_f64().ok_or_else(|| invalid("expected a number", value)),
+        Value::String(ref name) => match name.as_str() {
+            "NaN" => Ok(f64::NAN),
+            "Infinity" => Ok(f64::INFINITY),
+            "-Infinity" => Ok(f64::NEG_INFINITY),
+            _ => Err(invalid("expected a number", value)),
+        },
+        _ => Err(invalid("expected a number", value)),
+    }
+}
+
+fn integer(value: &Value, min: i64, max: i64) -> Result<i64> {
+    match value.as_i64() {
+        Some(integer) if integer >= min && integer <= max => Ok(integer),
+        _ => Err(invalid("expected an integer in range", value)),
+    }
+}
+
+fn integers(value: &Value, min: i64, max: i64) -> Result<Vec<i64>> {
+    match *value {
+        Value::Array(ref values) => values.iter().map(|value| integer(value, min, max)).collect(),
+        _ => Err(invalid("expected an array", value)),
+    }
+}
+
+/// Convert JSON produced by `to_json` in `JsonMode::Typed` back into a tag
+pub fn from_typed(json: &Value) -> Result<Tag> {
+    convert(json, |json| {
+        let type_name = typed_name(json)?;
+        if type_name == "End" {
+            return Ok(Node::Leaf(Tag::End));
+        }
+        let value = json.get("value").ok_or_else(|| invalid("expected an object with a value", json))?;
+
+        Ok(Node::Leaf(match type_name {
+            "Byte" => Tag::Byte(integer(value, i8::MIN.into(), i8::MAX.into())? as i8),
+            "Short" => Tag::Short(integer(value, i16::MIN.into(), i16::MAX.into())? as i16),
+            "Int" => Tag::Int(integer(value, i32::MIN.into(), i32::MAX.into())? as i32),
+            "Long" => Tag::Long(integer(value, i64::MIN, i64::MAX)?),
+            "Float" => Tag::Float(float_value(value)? as f32),
+            "Double" => Tag::Double(float_value(value)?),
+            "ByteArray" => Tag::ByteArray(integers(value, i8::MIN.into(), i8::MAX.into())?
+                .into_iter().map(|value| value as i8).collect()),
+            "String" => Tag::String(value.as_str().ok_or_else(|| invalid("expected a string", value))?.to_owned()),
+            "List" => {
+                let values = value.as_array().ok_or_else(|| invalid("expected an array", value))?;
+                return Ok(Node::Branch(values.iter().map(|value| ("", value)).collect()));
+            },
+            "Compound" => {
+                let pairs = value.as_array().ok_or_else(|| invalid("expected an array", value))?;
+                let mut entries = Vec::with_capacity(pairs.len());
+                for pair in pairs {
+                    match pair.as_array().map(Vec::as_slice) {
+                        Some([Value::String(name), value]) => entries.push((name.as_str(), value)),
+                        _ => return Err(invalid("expected a pair of a name and a tag", pair)),
+                    }
+                }
+                return Ok(Node::Branch(entries));
+            },
+            "IntArray" => Tag::IntArray(integers(value, i32::MIN.into(), i32::MAX.into())?
+                .into_iter().map(|value| value as i32).collect()),
+            "LongArray" => Tag::LongArray(integers(value, i64::MIN, i64::MAX)?),
+            _ => return Err(invalid("expected a tag type", json)),
+        }))
+    }, |json, children| Ok(match typed_name(json)? {
+        "Compound" => Tag::Compound(children.into_iter().map(|(name, tag)| (name.to_owned(), tag)).collect()),
+        _ => Tag::List(children.into_iter().map(|(_, tag)| tag).collect()),
+    }))
+}
+
+fn typed_name(json: &Value) -> Result<&str> {
+    json.get("type").and_then(Value::as_str).ok_or_else(|| invalid("expected an object with a type", json))
+}
+
+/// A node of a tree of tags or JSON values being converted by `convert`
+pub(crate) enum Node<'a, I: 'a, O> {
+    /// A node without children, which is already converted
+    Leaf(O),
+    /// A List, Compound, array or object, converted once its children are. Children
+    /// have the name of their entry, or an empty name within Lists and arrays.
+    Branch(Vec<(&'a str, &'a I)>),
+}
+
+/// A node of `convert` waiting for its children
+struct Branch<'a, I: 'a, O> {
+    node: &'a I,
+    children: vec::IntoIter<(&'a str, &'a I)>,
+    name: &'a str,
+    done: Vec<(&'a str, O)>,
+}
+
+/// Convert a tree, calling `open` with every node and `close` with every Branch once
+/// its children are converted. Shared with `text`.
+///
+/// Like `NbtWriter::write_tag` this uses an explicit stack rather than recursion, so
+/// arbitrarily deep structures can be converted regardless of the call stack size.
+pub(crate) fn convert<'a, I, O, F, G>(root: &'a I, mut open: F, mut close: G) -> Result<O>
+    where F: FnMut(&'a I) -> Result<Node<'a, I, O>>,
+          G: FnMut(&'a I, Vec<(&'a str, O)>) -> Result<O>
+{
+    let mut stack: Vec<Branch<I, O>> = Vec::new();
+    let mut node = root;
+    loop {
+        let mut converted = match open(node)? {
+            Node::Leaf(output) => Some(output),
+            Node::Branch(children) => {
+                let done = Vec::with_capacity(children.len());
+                stack.push(Branch { node, children: children.into_iter(), name: "", done });
+                None
+            },
+        };
+
+        loop {
+            let branch = match stack.last_mut() {
+                Some(branch) => branch,
+                None => return Ok(converted.expect("root was converted")),
+            };
+            if let Some(output) = converted.take() {
+                branch.done.push((branch.name, output));
+            }
+            if let Some((name, child)) = branch.children.next() {
+                branch.name = name;
+                node = child;
+                break;
+            }
+            let branch = stack.pop().expect("branch is on the stack");
+            converted = Some(close(branch.node, branch.done)?);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use read::read_file;
+    use std::fs::File;
+    use std::io::BufReader;
+    use std::thread;
+
+    #[test]
+    fn test_typed_round_trip() {
+        let mut reader = BufReader::new(File::open("./tests/bigtest.nbt").unwrap());
+        let root = read_file(&mut reader).unwrap().root;
+        let json = to_json(&root, JsonMode::Typed);
+        let text = json.to_string();
+        assert_eq!(from_json(&text.parse().unwrap(), JsonMode::Typed).unwrap(), root);
+
+        let special = Tag::List(vec![Tag::Float(f32::INFINITY), Tag::Float(f32::NEG_INFINITY), Tag::Float(0.1)]);
+        assert_eq!(from_typed(&to_typed(&special)).unwrap(), special);
+        match from_typed(&to_typed(&Tag::Double(f64::NAN))).unwrap() {
+            Tag::Double(value) => assert!(value.is_nan()),
+            tag => panic!("Expected Tag::Double, got Tag::{}", tag.type_name()),
+        }
+        assert!(from_typed(&"{\"type\": \"Byte\", \"value\": 128}".parse().unwrap()).is_err());
+
+        // Wrappers of heterogeneous lists use an empty name, which must not be confused
+        // with the End tag closing the Compound
+        let wrapped = Tag::List(vec![Tag::Compound(vec![(String::new(), Tag::Int(1)), (String::new(), Tag::End)])]);
+        assert_eq!(from_typed(&to_typed(&wrapped)).unwrap(), wrapped);
+        let duplicates = Tag::Compound(vec![(String::from("a"), Tag::Byte(1)), (String::from("a"), Tag::Byte(2))]);
+        assert_eq!(from_typed(&to_typed(&duplicates)).unwrap(), duplicates);
+        assert!(from_typed(&r#"{"type": "Compound", "value": {"a": {"type": "End"}}}"#.parse().unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_deep_nesting() {
+        const DEPTH: usize = 500;
+        let mut tag = Tag::List(Vec::new());
+        for _ in 1..DEPTH {
+            tag = Tag::List(vec![Tag::compound(vec![("", tag)])]);
+        }
+
+        // Like reading and writing, conversions must not use the call stack for nesting.
+        // Everything is dropped and compared outside of the thread, which does recurse.
+        let converted = thread::Builder::new().stack_size(64 * 1024).spawn(move || {
+            let typed = to_json(&tag, JsonMode::Typed);
+            let plain = to_json(&tag, JsonMode::Plain);
+            let reread = (from_json(&typed, JsonMode::Typed), from_json(&plain, JsonMode::Plain));
+            (tag, typed, plain, reread)
+        }).unwrap().join().unwrap();
+
+        let (tag, _typed, _plain, (typed, plain)) = converted;
+        assert_eq!(typed.unwrap(), tag);
+        assert!(plain.is_ok());
+    }
+
+    #[test]
+    fn test_plain() {
+        let json: Value = r#"{"name": "Steve", "health": 20.0, "onGround": true, "xp": 4294967296,
+            "pos": [1, 2, 3], "tags": [], "mixed": [1, "a", {"b": 2}], "missing": null}"#.parse().unwrap();
+        let tag = from_plain(&json).unwrap();
+        let compound = |entries: Vec<(&str, Tag)>| Tag::Compound(entries.into_iter()
+            .map(|(name, tag)| (String::from(name), tag))
+            .chain(Some((String::new(), Tag::End)))
+            .collect());
+        assert_eq!(tag, compound(vec![
+            ("name", Tag::String(String::from("Steve"))),
+            ("health", Tag::Double(20.0)),
+            ("onGround", Tag::Byte(1)),
+            ("xp", Tag::Long(4294967296)),
+            ("pos", Tag::IntArray(vec![1, 2, 3])),
+            ("tags", Tag::List(Vec::new())),
+            ("mixed", Tag::List(vec![
+                compound(vec![("", Tag::Int(1))]),
+                compound(vec![("", Tag::String(String::from("a")))]),
+                compound(vec![("b", Tag::Int(2))]),
+            ])),
+        ]));
+
+        let plain = to_json(&tag, JsonMode::Plain);
+        assert_eq!(plain["onGround"], Value::from(1));
+        assert_eq!(plain["pos"], json["pos"]);
+        assert!(plain.get("missing").is_none());
+    }
+}
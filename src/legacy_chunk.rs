@@ -0,0 +1,261 @@
+//! Chunks as stored in the region files of worlds saved before Minecraft 1.13.
+//!
+//! Everything is kept in a `Level` Compound. Sections hold a numeric block id for each
+//! of their 16x16x16 blocks in `Blocks`, with ids above 255 continued in the nibbles of
+//! `Add`, and a data value for each block in the nibbles of `Data`. Biomes are a byte
+//! for every column. Chunks of 1.13 to 1.17 are not supported, see `chunk`.
+use std::convert::TryFrom;
+use errors::*;
+
+use bits::NibbleArray;
+use chunk::SECTION_BLOCKS;
+use fields;
+use mcedit::LegacyBlock;
+use tag::Tag;
+
+/// First data version with flattened block states, 17w47a
+pub const FLATTENING_DATA_VERSION: i32 = 1451;
+
+const COLUMNS: usize = 16 * 16;
+
+/// Entries of the `Level` Compound which are read and written by `LegacyChunk` itself
+const KNOWN: &[&str] = &[
+    "xPos", "zPos", "LastUpdate", "InhabitedTime", "Sections", "Biomes", "HeightMap",
+    "Entities", "TileEntities",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LegacyChunk {
+    /// Only written since 1.9
+    pub data_version: Option<i32>,
+    /// Chunk coordinates, which are block coordinates divided by 16
+    pub x: i32,
+    pub z: i32,
+    pub last_update: i64,
+    pub inhabited_time: i64,
+    pub sections: Vec<LegacySection>,
+    /// Biome id of every column, ordered by z, then x
+    pub biomes: Option<Vec<u8>>,
+    /// Lowest y of every column where light from the sky is at full strength
+    pub height_map: Option<Vec<i32>>,
+    pub entities: Vec<Tag>,
+    pub tile_entities: Vec<Tag>,
+    /// Every other entry of `Level`, such as `TileTicks` or `TerrainPopulated`
+    pub other: Vec<(String, Tag)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LegacySection {
+    pub y: i8,
+    /// Lower eight bits of the block ids, ordered by y, then z, then x
+    pub blocks: Vec<u8>,
+    /// Upper four bits of the block ids, only present when a block needs them
+    pub add: Option<NibbleArray>,
+    pub data: NibbleArray,
+    pub block_light: NibbleArray,
+    /// Absent in dimensions without a sky
+    pub sky_light: Option<NibbleArray>,
+}
+
+impl LegacySection {
+    pub fn from_tag(tag: &Tag) -> Result<Self> {
+        let blocks = fields::byte_array(tag, "Blocks")?;
+        if blocks.len() != SECTION_BLOCKS {
+            return Err(fields::invalid("Blocks", "a byte for every block"));
+        }
+        let nibbles = |tag: &Tag, name: &str| fields::nibble_array(tag, name, SECTION_BLOCKS);
+        Ok(LegacySection {
+            y: i8::try_from(fields::int(tag, "Y")?).map_err(|_| fields::invalid("Y", "a Byte"))?,
+            blocks: blocks.iter().map(|&id| id as u8).collect(),
+            add: fields::optional(tag, "Add", nibbles)?,
+            data: nibbles(tag, "Data")?,
+            block_light: nibbles(tag, "BlockLight")?,
+            sky_light: fields::optional(tag, "SkyLight", nibbles)?,
+        })
+    }
+
+    pub fn to_tag(&self) -> Tag {
+        let mut tag = Tag::compound(vec![
+            ("Y", Tag::Byte(self.y)),
+            ("Blocks", Tag::ByteArray(self.blocks.iter().map(|&id| id as i8).collect())),
+        ]);
+        if let Some(ref add) = self.add {
+            tag.insert("Add", Tag::ByteArray(add.as_bytes().to_vec()));
+        }
+        tag.insert("Data", Tag::ByteArray(self.data.as_bytes().to_vec()));
+        tag.insert("BlockLight", Tag::ByteArray(self.block_light.as_bytes().to_vec()));
+        if let Some(ref light) = self.sky_light {
+            tag.insert("SkyLight", Tag::ByteArray(light.as_bytes().to_vec()));
+        }
+        tag
+    }
+
+    /// The block at coordinates within the section, each between 0 and 15
+    pub fn block(&self, x: usize, y: usize, z: usize) -> LegacyBlock {
+        let index = (y * 16 + z) * 16 + x;
+        let upper = self.add.as_ref().map_or(0, |add| add.get(index));
+        LegacyBlock {
+            id: u16::from(upper) << 8 | u16::from(self.blocks[index]),
+            data: self.data.get(index),
+        }
+    }
+}
+
+impl LegacyChunk {
+    /// Read a chunk as stored in a region file
+    pub fn from_tag(tag: &Tag) -> Result<Self> {
+        let data_version = fields::optional(tag, "DataVersion", fields::int)?;
+        if data_version.is_some_and(|version| version >= FLATTENING_DATA_VERSION) {
+            return Err(Error::UnsupportedFormat("chunk saved after the flattening"));
+        }
+        let level = fields::field(tag, "Level")?;
+
+        let biomes = match fields::optional(level, "Biomes", fields::byte_array)? {
+            Some(biomes) if biomes.len() == COLUMNS => Some(biomes.iter().map(|&biome| biome as u8).collect()),
+            Some(..) => return Err(fields::invalid("Biomes", "a byte for every column")),
+            None => None,
+        };
+        let height_map = match level.get("HeightMap").map(Tag::as_int_array) {
+            Some(Some(heights)) if heights.len() == COLUMNS => Some(heights.to_vec()),
+            Some(..) => return Err(fields::invalid("HeightMap", "an Int for every column")),
+            None => None,
+        };
+        let list = |name: &str| fields::optional(level, name, fields::list).map(|list| list.unwrap_or(&[]).to_vec());
+
+        Ok(LegacyChunk {
+            data_version,
+            x: fields::int(level, "xPos")?,
+            z: fields::int(level, "zPos")?,
+            last_update: fields::optional(level, "LastUpdate", fields::long)?.unwrap_or(0),
+            inhabited_time: fields::optional(level, "InhabitedTime", fields::long)?.unwrap_or(0),
+            sections: list("Sections")?.iter().map(LegacySection::from_tag).collect::<Result<_>>()?,
+            biomes,
+            height_map,
+            entities: list("Entities")?,
+            tile_entities: list("TileEntities")?,
+            other: fields::others(level, KNOWN),
+        })
+    }
+
+    pub fn to_tag(&self) -> Tag {
+        let mut level = Tag::compound(vec![
+            ("xPos", Tag::Int(self.x)),
+            ("zPos", Tag::Int(self.z)),
+            ("LastUpdate", Tag::Long(self.last_update)),
+            ("InhabitedTime", Tag::Long(self.inhabited_time)),
+            ("Sections", Tag::List(self.sections.iter().map(LegacySection::to_tag).collect())),
+        ]);
+        if let Some(ref biomes) = self.biomes {
+            level.insert("Biomes", Tag::ByteArray(biomes.iter().map(|&biome| biome as i8).collect()));
+        }
+        if let Some(ref heights) = self.height_map {
+            level.insert("HeightMap", Tag::IntArray(heights.clone()));
+        }
+        level.insert("Entities", Tag::List(self.entities.clone()));
+        level.insert("TileEntities", Tag::List(self.tile_entities.clone()));
+        for (name, other) in &self.other {
+            level.insert(name.as_str(), other.clone());
+        }
+
+        let mut tag = Tag::compound(vec![("Level", level)]);
+        if let Some(data_version) = self.data_version {
+            tag.insert("DataVersion", Tag::Int(data_version));
+        }
+        tag
+    }
+
+    pub fn section(&self, y: i8) -> Option<&LegacySection> {
+        self.sections.iter().find(|section| section.y == y)
+    }
+
+    fn holds(&self, x: i32, y: i32, z: i32) -> bool {
+        x >> 4 == self.x && z >> 4 == self.z && (0..256).contains(&y)
+    }
+
+    /// The block at world coordinates, if the chunk holds them. Sections which are not
+    /// stored only hold air.
+    pub fn block(&self, x: i32, y: i32, z: i32) -> Option<LegacyBlock> {
+        if !self.holds(x, y, z) {
+            return None;
+        }
+        Some(match self.section((y >> 4) as i8) {
+            Some(section) => section.block((x & 15) as usize, (y & 15) as usize, (z & 15) as usize),
+            None => LegacyBlock { id: 0, data: 0 },
+        })
+    }
+
+    /// The light levels from blocks and from the sky at world coordinates
+    pub fn light(&self, x: i32, y: i32, z: i32) -> Option<(u8, Option<u8>)> {
+        if !self.holds(x, y, z) {
+            return None;
+        }
+        let section = self.section((y >> 4) as i8)?;
+        let index = (((y & 15) * 16 + (z & 15)) * 16 + (x & 15)) as usize;
+        Some((section.block_light.get(index), section.sky_light.as_ref().map(|light| light.get(index))))
+    }
+
+    /// The biome id of the column at world coordinates
+    pub fn biome(&self, x: i32, z: i32) -> Option<u8> {
+        if x >> 4 != self.x || z >> 4 != self.z {
+            return None;
+        }
+        self.biomes.as_ref().map(|biomes| biomes[((z & 15) * 16 + (x & 15)) as usize])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_legacy_chunk() {
+        // Stone at the bottom, red wool above it and a block with an id above 255
+        let mut blocks = vec![0; SECTION_BLOCKS];
+        blocks[0] = 1;
+        blocks[256] = 35;
+        blocks[512 + 17] = 2;
+        let mut data = NibbleArray::new(SECTION_BLOCKS);
+        data.set(256, 14);
+        let mut add = NibbleArray::new(SECTION_BLOCKS);
+        add.set(512 + 17, 1);
+        let mut light = NibbleArray::new(SECTION_BLOCKS);
+        light.set(256, 15);
+
+        let tag = Tag::compound(vec![
+            ("DataVersion", Tag::Int(1343)),
+            ("Level", Tag::compound(vec![
+                ("xPos", Tag::Int(1)),
+                ("zPos", Tag::Int(-1)),
+                ("LastUpdate", Tag::Long(500)),
+                ("TerrainPopulated", Tag::Byte(1)),
+                ("Biomes", Tag::ByteArray(vec![4; COLUMNS])),
+                ("Sections", Tag::List(vec![Tag::compound(vec![
+                    ("Y", Tag::Byte(0)),
+                    ("Blocks", Tag::ByteArray(blocks)),
+                    ("Add", Tag::ByteArray(add.into_bytes())),
+                    ("Data", Tag::ByteArray(data.into_bytes())),
+                    ("BlockLight", Tag::ByteArray(light.into_bytes())),
+                ])])),
+            ])),
+        ]);
+        let chunk = LegacyChunk::from_tag(&tag).unwrap();
+        assert_eq!(chunk.other, vec![(String::from("TerrainPopulated"), Tag::Byte(1))]);
+        assert_eq!(LegacyChunk::from_tag(&chunk.to_tag()).unwrap(), chunk);
+
+        assert_eq!(chunk.block(16, 0, -16), Some(LegacyBlock { id: 1, data: 0 }));
+        assert_eq!(chunk.block(16, 1, -16), Some(LegacyBlock { id: 35, data: 14 }));
+        assert_eq!(chunk.block(17, 2, -15), Some(LegacyBlock { id: 258, data: 0 }));
+        assert_eq!(chunk.block(16, 100, -16), Some(LegacyBlock { id: 0, data: 0 }));
+        assert_eq!(chunk.block(0, 0, -16), None);
+        assert_eq!(chunk.light(16, 1, -16), Some((15, None)));
+        assert_eq!(chunk.biome(31, -1), Some(4));
+
+        let mut section = tag.get("Level").and_then(|level| level.get("Sections")).and_then(Tag::as_list).unwrap()[0].clone();
+        section.insert("Y", Tag::Int(128));
+        assert!(LegacySection::from_tag(&section).is_err());
+
+        let mut flattened = tag;
+        flattened.insert("DataVersion", Tag::Int(1519));
+        assert!(LegacyChunk::from_tag(&flattened).is_err());
+    }
+}
@@ -0,0 +1,208 @@
+//! The `level.dat` of a Java Edition world, holding its name, spawn point, game rules
+//! and the settings it was generated with within a `Data` Compound. Singleplayer
+//! worlds also store the data of their player here.
+use std::io::BufRead;
+use std::io::Write;
+use errors::*;
+
+use fields;
+use read::read_file;
+use read::NBTFile;
+use tag::Compression;
+use tag::Tag;
+use write::write_file;
+
+/// Entries of `Data` which are read and written by `LevelData` itself
+const KNOWN: &[&str] = &[
+    "LevelName", "DataVersion", "Version", "SpawnX", "SpawnY", "SpawnZ", "GameRules",
+    "WorldGenSettings", "Player",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LevelData {
+    pub name: String,
+    /// Only written since 1.9
+    pub data_version: Option<i32>,
+    /// Only written since 1.9
+    pub version: Option<GameVersion>,
+    pub spawn: [i32; 3],
+    /// Game rules by name, their values are Strings such as `"true"` or `"3"`
+    pub game_rules: Vec<(String, Tag)>,
+    /// Seed, dimensions and generator settings, since 1.16
+    pub world_gen_settings: Option<Tag>,
+    /// The player of a singleplayer world
+    pub player: Option<Tag>,
+    /// Every other entry of `Data`, such as `GameType` or `DayTime`
+    pub other: Vec<(String, Tag)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// The version of Minecraft a world was last opened with
+pub struct GameVersion {
+    /// Data version of the game, the same as `LevelData::data_version`
+    pub id: i32,
+    /// Such as `1.20.4` or `23w51b`
+    pub name: String,
+    /// `main` for every version but experimental snapshots
+    pub series: String,
+    pub snapshot: bool,
+}
+
+impl GameVersion {
+    pub fn from_tag(tag: &Tag) -> Result<Self> {
+        Ok(GameVersion {
+            id: fields::int(tag, "Id")?,
+            name: fields::string(tag, "Name")?.to_owned(),
+            series: tag.get("Series").and_then(Tag::as_str).unwrap_or("main").to_owned(),
+            snapshot: tag.get("Snapshot").and_then(Tag::as_bool).unwrap_or(false),
+        })
+    }
+
+    pub fn to_tag(&self) -> Tag {
+        Tag::compound(vec![
+            ("Id", Tag::Int(self.id)),
+            ("Name", Tag::from(self.name.as_str())),
+            ("Series", Tag::from(self.series.as_str())),
+            ("Snapshot", Tag::from(self.snapshot)),
+        ])
+    }
+}
+
+impl LevelData {
+    /// Read a `level.dat`, the compression is detected automatically
+    pub fn read<R: BufRead>(reader: &mut R) -> Result<Self> {
+        LevelData::from_file(&read_file(reader)?)
+    }
+
+    /// Write a gzip compressed `level.dat`, as Minecraft does
+    pub fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+        write_file(writer, &self.to_file())
+    }
+
+    pub fn from_file(file: &NBTFile) -> Result<Self> {
+        let root = file.root_tag().ok_or_else(|| Error::MissingTag(String::from("Data")))?;
+        LevelData::from_tag(fields::field(root, "Data")?)
+    }
+
+    pub fn to_file(&self) -> NBTFile {
+        NBTFile::with_root(Compression::Gzip, Tag::compound(vec![("Data", self.to_tag())]))
+    }
+
+    /// Read the `Data` Compound
+    pub fn from_tag(tag: &Tag) -> Result<Self> {
+        let game_rules = match tag.get("GameRules") {
+            Some(rules) => rules.entries()
+                .ok_or_else(|| fields::invalid("GameRules", "a Compound"))?
+                .map(|(name, value)| (name.to_owned(), value.clone()))
+                .collect(),
+            None => Vec::new(),
+        };
+        Ok(LevelData {
+            name: fields::string(tag, "LevelName")?.to_owned(),
+            data_version: fields::optional(tag, "DataVersion", fields::int)?,
+            version: fields::optional(tag, "Version", |tag, name| GameVersion::from_tag(fields::field(tag, name)?))?,
+            spawn: [fields::int(tag, "SpawnX")?, fields::int(tag, "SpawnY")?, fields::int(tag, "SpawnZ")?],
+            game_rules,
+            world_gen_settings: tag.get("WorldGenSettings").cloned(),
+            player: tag.get("Player").cloned(),
+            other: fields::others(tag, KNOWN),
+        })
+    }
+
+    /// Write the `Data` Compound
+    pub fn to_tag(&self) -> Tag {
+        let mut tag = Tag::compound(vec![("LevelName", Tag::from(self.name.as_str()))]);
+        if let Some(data_version) = self.data_version {
+            tag.insert("DataVersion", Tag::Int(data_version));
+        }
+        if let Some(ref version) = self.version {
+            tag.insert("Version", version.to_tag());
+        }
+        tag.insert("SpawnX", Tag::Int(self.spawn[0]));
+        tag.insert("SpawnY", Tag::Int(self.spawn[1]));
+        tag.insert("SpawnZ", Tag::Int(self.spawn[2]));
+        tag.insert("GameRules", Tag::compound(self.game_rules.iter().map(|(name, value)| (name.as_str(), value.clone()))));
+        if let Some(ref settings) = self.world_gen_settings {
+            tag.insert("WorldGenSettings", settings.clone());
+        }
+        if let Some(ref player) = self.player {
+            tag.insert("Player", player.clone());
+        }
+        for (name, other) in &self.other {
+            tag.insert(name.as_str(), other.clone());
+        }
+        tag
+    }
+
+    /// The value of a game rule, such as `doDaylightCycle`
+    pub fn game_rule(&self, name: &str) -> Option<&str> {
+        self.game_rules.iter()
+            .find(|(rule, _)| rule == name)
+            .and_then(|(_, value)| value.as_str())
+    }
+
+    /// Set a game rule, adding it if the world does not have it yet
+    pub fn set_game_rule(&mut self, name: &str, value: &str) {
+        match self.game_rules.iter_mut().find(|(rule, _)| rule == name) {
+            Some((_, existing)) => *existing = Tag::from(value),
+            None => self.game_rules.push((name.to_owned(), Tag::from(value))),
+        }
+    }
+
+    /// The seed of the world, stored in `WorldGenSettings` since 1.16 and as
+    /// `RandomSeed` before
+    pub fn seed(&self) -> Option<i64> {
+        match self.world_gen_settings {
+            Some(ref settings) => settings.get("seed").and_then(Tag::as_i64),
+            None => self.other.iter().find(|(name, _)| name == "RandomSeed").and_then(|(_, seed)| seed.as_i64()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_level_data() {
+        let data = Tag::compound(vec![
+            ("DataVersion", Tag::Int(3700)),
+            ("Version", Tag::compound(vec![
+                ("Id", Tag::Int(3700)),
+                ("Name", Tag::from("1.20.4")),
+                ("Series", Tag::from("main")),
+                ("Snapshot", Tag::Byte(0)),
+            ])),
+            ("LevelName", Tag::from("New World")),
+            ("SpawnX", Tag::Int(16)),
+            ("SpawnY", Tag::Int(70)),
+            ("SpawnZ", Tag::Int(-32)),
+            ("GameRules", Tag::compound(vec![("doDaylightCycle", Tag::from("true"))])),
+            ("WorldGenSettings", Tag::compound(vec![("seed", Tag::Long(-42))])),
+            ("DayTime", Tag::Long(6000)),
+            ("GameType", Tag::Int(1)),
+        ]);
+        let file = NBTFile::with_root(Compression::Gzip, Tag::compound(vec![("Data", data)]));
+        let mut written = Vec::new();
+        write_file(&mut written, &file).unwrap();
+
+        let mut level = LevelData::read(&mut Cursor::new(written)).unwrap();
+        assert_eq!(level.name, "New World");
+        assert_eq!(level.spawn, [16, 70, -32]);
+        assert_eq!(level.version.as_ref().map(|version| version.name.as_str()), Some("1.20.4"));
+        assert_eq!(level.game_rule("doDaylightCycle"), Some("true"));
+        assert_eq!(level.seed(), Some(-42));
+        assert_eq!(level.other.len(), 2);
+
+        level.set_game_rule("doDaylightCycle", "false");
+        level.set_game_rule("keepInventory", "true");
+        let mut written = Vec::new();
+        level.write(&mut written).unwrap();
+        assert_eq!(&written[..2], &[0x1f, 0x8b]);
+        let reread = LevelData::read(&mut Cursor::new(written)).unwrap();
+        assert_eq!(reread, level);
+        assert_eq!(reread.game_rule("doDaylightCycle"), Some("false"));
+        assert_eq!(reread.to_tag().get("DayTime"), Some(&Tag::Long(6000)));
+    }
+}
@@ -1,13 +1,40 @@
-#[macro_use]
-extern crate error_chain;
-
 extern crate flate2;
 extern crate byteorder;
+extern crate lz4_flex;
+extern crate twox_hash;
+extern crate serde_json;
+#[cfg(feature = "uuid")]
+extern crate uuid as uuid_crate;
 
 pub mod tag;
 pub mod read;
+pub mod write;
+pub mod json;
+pub mod text;
+pub mod block;
+pub mod structure;
+pub mod sponge;
+pub mod mcedit;
+pub mod litematic;
+pub mod mcstructure;
+pub mod bits;
+pub mod chunk;
+pub mod legacy_chunk;
+pub mod level;
+pub mod region;
+pub mod world;
+pub mod item;
+pub mod player;
+pub mod uuid;
+pub mod fixer;
+mod fields;
+mod lz4;
 
 pub use tag::Tag;
 pub use read::*;
+pub use write::*;
+
+pub mod errors;
 
-pub mod errors;
\ No newline at end of file
+pub use errors::Error;
+pub use errors::Result;
\ No newline at end of file
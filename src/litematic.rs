@@ -0,0 +1,337 @@
+//! Litematica schematics, the `.litematic` files which are gzip compressed and hold
+//! any number of named regions.
+//!
+//! The blocks of a region are palette indices packed into `BlockStates` using at least
+//! two bits each, with entries spanning longs, ordered by x, then z, then y. Regions
+//! may have negative sizes, in which case they extend from their position towards
+//! negative coordinates.
+use std::io::BufRead;
+use std::io::Write;
+use errors::*;
+
+use bits;
+use bits::BitStorage;
+use bits::Layout;
+use block::BlockState;
+use fields;
+use read::read_file;
+use read::NBTFile;
+use tag::Compression;
+use tag::Tag;
+use write::write_file;
+
+/// Version written by current releases of Litematica
+pub const VERSION: i32 = 6;
+
+const MIN_BITS: u32 = 2;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Litematic {
+    pub version: i32,
+    pub sub_version: Option<i32>,
+    pub data_version: i32,
+    pub metadata: Metadata,
+    pub regions: Vec<(String, Region)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+/// Descriptive metadata, the totals and enclosing size are computed when writing
+pub struct Metadata {
+    pub name: String,
+    pub author: String,
+    pub description: String,
+    /// Milliseconds since the Unix epoch
+    pub time_created: i64,
+    pub time_modified: i64,
+    /// Rendered preview as ARGB pixels
+    pub preview_image: Option<Vec<i32>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Region {
+    pub position: [i32; 3],
+    /// Size along each axis, negative sizes extend towards negative coordinates
+    pub size: [i32; 3],
+    pub palette: Vec<BlockState>,
+    /// Palette index of every block, see `Region::index`
+    pub blocks: BitStorage,
+    pub tile_entities: Vec<Tag>,
+    pub entities: Vec<Tag>,
+    pub pending_block_ticks: Vec<Tag>,
+    pub pending_fluid_ticks: Vec<Tag>,
+}
+
+fn xyz(compound: &Tag, name: &str) -> Result<[i32; 3]> {
+    let tag = fields::field(compound, name)?;
+    Ok([fields::int(tag, "x")?, fields::int(tag, "y")?, fields::int(tag, "z")?])
+}
+
+fn xyz_tag(values: [i32; 3]) -> Tag {
+    Tag::compound(vec![("x", Tag::Int(values[0])), ("y", Tag::Int(values[1])), ("z", Tag::Int(values[2]))])
+}
+
+/// Number of blocks within a region of the given size, or `None` if it overflows
+fn volume(size: [i32; 3]) -> Option<usize> {
+    size.iter().try_fold(1usize, |volume, axis| volume.checked_mul(axis.unsigned_abs() as usize))
+}
+
+fn tags(compound: &Tag, name: &str) -> Result<Vec<Tag>> {
+    match compound.get(name) {
+        Some(..) => fields::list(compound, name).map(<[Tag]>::to_vec),
+        None => Ok(Vec::new()),
+    }
+}
+
+impl Litematic {
+    pub fn new(data_version: i32) -> Self {
+        Litematic {
+            version: VERSION,
+            sub_version: None,
+            data_version,
+            metadata: Metadata::default(),
+            regions: Vec::new(),
+        }
+    }
+
+    /// Read a schematic file, the compression is detected automatically
+    pub fn read<R: BufRead>(reader: &mut R) -> Result<Self> {
+        let file = read_file(reader)?;
+        Litematic::from_tag(file.root_tag().ok_or_else(|| Error::MissingTag(String::from("Regions")))?)
+    }
+
+    /// Write a gzip compressed schematic file, as Litematica does
+    pub fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+        write_file(writer, &NBTFile::with_root(Compression::Gzip, self.to_tag()))
+    }
+
+    pub fn from_tag(tag: &Tag) -> Result<Self> {
+        let metadata = fields::field(tag, "Metadata")?;
+        let text = |name: &str| metadata.get(name).and_then(Tag::as_str).unwrap_or("").to_owned();
+        let time = |name: &str| metadata.get(name).and_then(Tag::as_i64).unwrap_or(0);
+
+        let regions_tag = fields::field(tag, "Regions")?;
+        let mut regions = Vec::new();
+        for (name, region) in regions_tag.entries().ok_or_else(|| fields::invalid("Regions", "a Compound"))? {
+            regions.push((name.to_owned(), Region::from_tag(region)?));
+        }
+
+        Ok(Litematic {
+            version: fields::int(tag, "Version")?,
+            sub_version: tag.get("SubVersion").and_then(Tag::as_i32),
+            data_version: fields::int(tag, "MinecraftDataVersion")?,
+            metadata: Metadata {
+                name: text("Name"),
+                author: text("Author"),
+                description: text("Description"),
+                time_created: time("TimeCreated"),
+                time_modified: time("TimeModified"),
+                preview_image: metadata.get("PreviewImageData").and_then(Tag::as_int_array).map(<[i32]>::to_vec),
+            },
+            regions,
+        })
+    }
+
+    pub fn to_tag(&self) -> Tag {
+        let mut min = [i32::MAX; 3];
+        let mut max = [i32::MIN; 3];
+        for (_, region) in &self.regions {
+            let (start, end) = region.bounds();
+            for axis in 0..3 {
+                min[axis] = min[axis].min(start[axis]);
+                max[axis] = max[axis].max(end[axis]);
+            }
+        }
+        let enclosing = match self.regions.len() {
+            0 => [0; 3],
+            _ => [max[0] - min[0] + 1, max[1] - min[1] + 1, max[2] - min[2] + 1],
+        };
+        let total_volume: usize = self.regions.iter().map(|(_, region)| region.volume()).sum();
+        let total_blocks: usize = self.regions.iter().map(|(_, region)| region.non_air_blocks()).sum();
+
+        let mut metadata = Tag::compound(vec![
+            ("Name", Tag::from(self.metadata.name.as_str())),
+            ("Author", Tag::from(self.metadata.author.as_str())),
+            ("Description", Tag::from(self.metadata.description.as_str())),
+            ("RegionCount", Tag::Int(self.regions.len() as i32)),
+            ("TotalVolume", Tag::Int(total_volume as i32)),
+            ("TotalBlocks", Tag::Int(total_blocks as i32)),
+            ("TimeCreated", Tag::Long(self.metadata.time_created)),
+            ("TimeModified", Tag::Long(self.metadata.time_modified)),
+            ("EnclosingSize", xyz_tag(enclosing)),
+        ]);
+        if let Some(ref image) = self.metadata.preview_image {
+            metadata.insert("PreviewImageData", Tag::IntArray(image.clone()));
+        }
+
+        let mut tag = Tag::compound(vec![
+            ("MinecraftDataVersion", Tag::Int(self.data_version)),
+            ("Version", Tag::Int(self.version)),
+        ]);
+        if let Some(sub_version) = self.sub_version {
+            tag.insert("SubVersion", Tag::Int(sub_version));
+        }
+        tag.insert("Metadata", metadata);
+        tag.insert("Regions", Tag::compound(self.regions.iter()
+            .map(|(name, region)| (name.as_str(), region.to_tag()))));
+        tag
+    }
+}
+
+impl Region {
+    /// Create a region of the given size filled with air
+    pub fn new(position: [i32; 3], size: [i32; 3]) -> Result<Self> {
+        let volume = volume(size).ok_or_else(|| fields::invalid("Size", "a size with a volume that fits in memory"))?;
+        Ok(Region {
+            position,
+            size,
+            palette: vec![BlockState::air()],
+            blocks: BitStorage::new(Layout::Spanning, MIN_BITS, volume)?,
+            tile_entities: Vec::new(),
+            entities: Vec::new(),
+            pending_block_ticks: Vec::new(),
+            pending_fluid_ticks: Vec::new(),
+        })
+    }
+
+    pub fn from_tag(tag: &Tag) -> Result<Self> {
+        let size = xyz(tag, "Size")?;
+        let palette: Vec<BlockState> = fields::list(tag, "BlockStatePalette")?.iter()
+            .map(BlockState::from_tag)
+            .collect::<Result<_>>()?;
+        // The size is only trusted once it matches the length of the block states
+        let volume = volume(size).ok_or_else(|| fields::invalid("Size", "a size with a volume that fits in memory"))?;
+        let bits = bits::bits_for(palette.len(), MIN_BITS);
+        let states = fields::long_array(tag, "BlockStates")?.to_vec();
+        let blocks = BitStorage::from_longs(Layout::Spanning, bits, volume, states)?;
+        if blocks.iter().any(|index| index as usize >= palette.len()) {
+            return Err(fields::invalid("BlockStates", "indices into the palette"));
+        }
+
+        Ok(Region {
+            position: xyz(tag, "Position")?,
+            size,
+            palette,
+            blocks,
+            tile_entities: tags(tag, "TileEntities")?,
+            entities: tags(tag, "Entities")?,
+            pending_block_ticks: tags(tag, "PendingBlockTicks")?,
+            pending_fluid_ticks: tags(tag, "PendingFluidTicks")?,
+        })
+    }
+
+    pub fn to_tag(&self) -> Tag {
+        Tag::compound(vec![
+            ("Position", xyz_tag(self.position)),
+            ("Size", xyz_tag(self.size)),
+            ("BlockStatePalette", Tag::List(self.palette.iter().map(BlockState::to_tag).collect())),
+            ("BlockStates", Tag::LongArray(self.blocks.as_longs().to_vec())),
+            ("TileEntities", Tag::List(self.tile_entities.clone())),
+            ("Entities", Tag::List(self.entities.clone())),
+            ("PendingBlockTicks", Tag::List(self.pending_block_ticks.clone())),
+            ("PendingFluidTicks", Tag::List(self.pending_fluid_ticks.clone())),
+        ])
+    }
+
+    fn dimensions(&self) -> [usize; 3] {
+        [self.size[0].unsigned_abs() as usize, self.size[1].unsigned_abs() as usize, self.size[2].unsigned_abs() as usize]
+    }
+
+    pub fn volume(&self) -> usize {
+        self.dimensions().iter().product()
+    }
+
+    /// The lowest and highest corner of the region, both inclusive
+    pub fn bounds(&self) -> ([i32; 3], [i32; 3]) {
+        let mut start = self.position;
+        let mut end = self.position;
+        for axis in 0..3 {
+            match self.size[axis] {
+                size if size < 0 => start[axis] += size + 1,
+                size if size > 0 => end[axis] += size - 1,
+                _ => {},
+            }
+        }
+        (start, end)
+    }
+
+    /// Index within `blocks` of a position relative to the lowest corner of the region
+    pub fn index(&self, x: usize, y: usize, z: usize) -> Option<usize> {
+        let [width, height, length] = self.dimensions();
+        if x >= width || y >= height || z >= length {
+            return None;
+        }
+        Some(x + z * width + y * width * length)
+    }
+
+    pub fn block(&self, x: usize, y: usize, z: usize) -> Option<&BlockState> {
+        self.index(x, y, z).map(|index| &self.palette[self.blocks.get(index) as usize])
+    }
+
+    /// Set the block at a position relative to the lowest corner of the region, adding
+    /// its state to the palette and widening the entries when needed
+    ///
+    /// # Panics
+    ///
+    /// Panics if the position is outside of the region
+    pub fn set_block(&mut self, x: usize, y: usize, z: usize, state: BlockState) {
+        let index = self.index(x, y, z).expect("position outside of the region");
+        let palette_index = match self.palette.iter().position(|existing| *existing == state) {
+            Some(palette_index) => palette_index,
+            None => {
+                self.palette.push(state);
+                self.blocks.grow_for_palette(self.palette.len(), MIN_BITS);
+                self.palette.len() - 1
+            },
+        };
+        self.blocks.set(index, palette_index as u32);
+    }
+
+    fn non_air_blocks(&self) -> usize {
+        self.blocks.iter().filter(|&index| self.palette[index as usize].name != ::block::AIR).count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_litematic() {
+        let mut litematic = Litematic::new(3700);
+        litematic.metadata.name = String::from("Tower");
+        litematic.metadata.author = String::from("builder");
+
+        // Enough distinct states that the entries need to grow past two bits
+        let mut region = Region::new([10, 64, -5], [3, -4, 2]).unwrap();
+        for y in 0..4 {
+            region.set_block(1, y, 1, BlockState::new(format!("minecraft:block_{}", y)));
+        }
+        assert_eq!(region.blocks.bits(), 3);
+        region.tile_entities.push(Tag::compound(vec![("x", Tag::Int(1)), ("y", Tag::Int(0)), ("z", Tag::Int(1))]));
+        litematic.regions.push((String::from("Tower"), region));
+
+        let mut written = Vec::new();
+        litematic.write(&mut written).unwrap();
+        let reread = Litematic::read(&mut Cursor::new(written)).unwrap();
+        assert_eq!(reread, litematic);
+
+        let (_, region) = &reread.regions[0];
+        assert_eq!(region.block(1, 2, 1), Some(&BlockState::new("minecraft:block_2")));
+        assert_eq!(region.block(0, 0, 0), Some(&BlockState::air()));
+        assert_eq!(region.bounds(), ([10, 61, -5], [12, 64, -4]));
+
+        let metadata = litematic.to_tag();
+        let metadata = metadata.get("Metadata").unwrap();
+        assert_eq!(metadata.get("TotalVolume"), Some(&Tag::Int(24)));
+        assert_eq!(metadata.get("TotalBlocks"), Some(&Tag::Int(4)));
+
+        // The size must match the block states before anything is allocated for it
+        let mut tag = reread.regions[0].1.to_tag();
+        tag.insert("Size", xyz_tag([i32::MIN, i32::MIN, i32::MIN]));
+        assert!(Region::from_tag(&tag).is_err());
+        tag.insert("Size", xyz_tag([1 << 20, 1 << 20, 1 << 20]));
+        assert!(Region::from_tag(&tag).is_err());
+        assert!(Region::new([0; 3], [i32::MIN, i32::MIN, i32::MIN]).is_err());
+    }
+}
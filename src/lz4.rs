@@ -0,0 +1,209 @@
+//! The LZ4 block stream written by lz4-java's `LZ4BlockOutputStream`, which is what
+//! Minecraft uses whenever it stores NBT data using LZ4 compression.
+//!
+//! Every block starts with a 21 byte header: the `LZ4Block` magic, a token holding the
+//! compression method and block size, followed by the compressed length, the decompressed
+//! length and a checksum of the decompressed contents as little-endian integers.
+//! A block with a decompressed length of zero marks the end of the stream.
+use std::cmp;
+use std::io;
+use std::io::Read;
+use std::io::Write;
+use byteorder::LittleEndian;
+use byteorder::ReadBytesExt;
+use byteorder::WriteBytesExt;
+use twox_hash::XxHash32;
+
+pub const MAGIC: &[u8; 8] = b"LZ4Block";
+
+const METHOD_RAW: u8 = 0x10;
+const METHOD_LZ4: u8 = 0x20;
+const COMPRESSION_LEVEL_BASE: u8 = 10;
+const DEFAULT_BLOCK_SIZE: usize = 1 << 16;
+const CHECKSUM_SEED: u32 = 0x9747_b28c;
+
+fn checksum(data: &[u8]) -> u32 {
+    XxHash32::oneshot(CHECKSUM_SEED, data) & 0x0fff_ffff
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("corrupted LZ4 block stream: {}", message))
+}
+
+/// Decompresses an LZ4 block stream, concatenated streams are read back to back
+pub struct Lz4BlockReader<R> {
+    inner: R,
+    buffer: Vec<u8>,
+    position: usize,
+}
+
+impl<R: Read> Lz4BlockReader<R> {
+    pub fn new(inner: R) -> Self {
+        Lz4BlockReader {
+            inner,
+            buffer: Vec::new(),
+            position: 0,
+        }
+    }
+
+    /// Read the next block into the buffer, returning `false` at the end of the input
+    fn next_block(&mut self) -> io::Result<bool> {
+        loop {
+            let mut magic = [0u8; 8];
+            let mut read = 0;
+            while read < magic.len() {
+                match self.inner.read(&mut magic[read..])? {
+                    0 if read == 0 => return Ok(false),
+                    0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+                    n => read += n,
+                }
+            }
+            if &magic != MAGIC {
+                return Err(invalid_data("bad magic"));
+            }
+
+            let token = self.inner.read_u8()?;
+            let method = token & 0xf0;
+            let block_size = 1usize << (COMPRESSION_LEVEL_BASE + (token & 0x0f));
+            let compressed_length = self.inner.read_i32::<LittleEndian>()?;
+            let length = self.inner.read_i32::<LittleEndian>()?;
+            let expected_checksum = self.inner.read_u32::<LittleEndian>()?;
+
+            if length < 0 || compressed_length < 0 || length as usize > block_size
+                || compressed_length as usize > ::lz4_flex::block::get_maximum_output_size(block_size)
+                || (method == METHOD_RAW && compressed_length != length) {
+                return Err(invalid_data("bad block lengths"));
+            }
+            if length == 0 {
+                // End of this stream, another one may follow it. An end marker claiming
+                // compressed contents would leave them to be parsed as the next header.
+                if compressed_length != 0 {
+                    return Err(invalid_data("end of stream with contents"));
+                }
+                continue;
+            }
+
+            let mut compressed = Vec::with_capacity(compressed_length as usize);
+            (&mut self.inner).take(compressed_length as u64).read_to_end(&mut compressed)?;
+            if compressed.len() != compressed_length as usize {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+
+            self.buffer = match method {
+                METHOD_RAW => compressed,
+                METHOD_LZ4 => ::lz4_flex::block::decompress(&compressed, length as usize)
+                    .map_err(|_| invalid_data("bad block contents"))?,
+                _ => return Err(invalid_data("unknown compression method")),
+            };
+            self.position = 0;
+
+            if self.buffer.len() != length as usize || checksum(&self.buffer) != expected_checksum {
+                return Err(invalid_data("checksum mismatch"));
+            }
+            return Ok(true);
+        }
+    }
+}
+
+impl<R: Read> Read for Lz4BlockReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position == self.buffer.len() && !self.next_block()? {
+            return Ok(0);
+        }
+        let length = cmp::min(buf.len(), self.buffer.len() - self.position);
+        buf[..length].copy_from_slice(&self.buffer[self.position..self.position + length]);
+        self.position += length;
+        Ok(length)
+    }
+}
+
+/// Compresses data into an LZ4 block stream, `finish` must be called to terminate it
+pub struct Lz4BlockWriter<W: Write> {
+    inner: W,
+    buffer: Vec<u8>,
+}
+
+impl<W: Write> Lz4BlockWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Lz4BlockWriter {
+            inner,
+            buffer: Vec::with_capacity(DEFAULT_BLOCK_SIZE),
+        }
+    }
+
+    fn write_block(&mut self) -> io::Result<()> {
+        let compressed = ::lz4_flex::block::compress(&self.buffer);
+        let (method, contents) = if compressed.len() < self.buffer.len() {
+            (METHOD_LZ4, &compressed[..])
+        } else {
+            (METHOD_RAW, &self.buffer[..])
+        };
+        write_header(&mut self.inner, method, contents.len(), self.buffer.len(), checksum(&self.buffer))?;
+        self.inner.write_all(contents)?;
+        self.buffer.clear();
+        Ok(())
+    }
+
+    /// Write any buffered data followed by the end of stream marker
+    pub fn finish(mut self) -> io::Result<W> {
+        if !self.buffer.is_empty() {
+            self.write_block()?;
+        }
+        write_header(&mut self.inner, METHOD_RAW, 0, 0, 0)?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+fn write_header<W: Write>(writer: &mut W, method: u8, compressed_length: usize, length: usize, checksum: u32) -> io::Result<()> {
+    let level = DEFAULT_BLOCK_SIZE.trailing_zeros() as u8 - COMPRESSION_LEVEL_BASE;
+    writer.write_all(MAGIC)?;
+    writer.write_u8(method | level)?;
+    writer.write_i32::<LittleEndian>(compressed_length as i32)?;
+    writer.write_i32::<LittleEndian>(length as i32)?;
+    writer.write_u32::<LittleEndian>(checksum)
+}
+
+impl<W: Write> Write for Lz4BlockWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let length = cmp::min(buf.len(), DEFAULT_BLOCK_SIZE - self.buffer.len());
+        self.buffer.extend_from_slice(&buf[..length]);
+        if self.buffer.len() == DEFAULT_BLOCK_SIZE {
+            self.write_block()?;
+        }
+        Ok(length)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.buffer.is_empty() {
+            self.write_block()?;
+        }
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_end_of_stream() {
+        let mut stream = Vec::new();
+        let mut writer = Lz4BlockWriter::new(&mut stream);
+        writer.write_all(b"first").unwrap();
+        writer.finish().unwrap();
+        let mut writer = Lz4BlockWriter::new(&mut stream);
+        writer.write_all(b" second").unwrap();
+        writer.finish().unwrap();
+        let mut contents = Vec::new();
+        Lz4BlockReader::new(&stream[..]).read_to_end(&mut contents).unwrap();
+        assert_eq!(contents, b"first second");
+
+        // An end marker must not claim any compressed bytes
+        let mut corrupted = Vec::new();
+        write_header(&mut corrupted, METHOD_LZ4, 4, 0, 0).unwrap();
+        corrupted.extend_from_slice(b"junk");
+        let error = Lz4BlockReader::new(&corrupted[..]).read_to_end(&mut Vec::new()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
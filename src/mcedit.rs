@@ -0,0 +1,207 @@
+//! Legacy `.schematic` files written by MCEdit and WorldEdit before Minecraft 1.13.
+//!
+//! Blocks are stored as numeric ids in `Blocks`, with the upper four bits of ids above
+//! 255 packed two per byte into `AddBlocks`, and a data value for every block in
+//! `Data`. Blocks are ordered by x, then z, then y, as in Sponge schematics.
+use std::collections::HashMap;
+use std::io::BufRead;
+use errors::*;
+
+use bits::NibbleArray;
+use block::BlockState;
+use fields;
+use read::read_file;
+use sponge::Schematic;
+use sponge::SchematicEntity;
+use tag::Tag;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// A numeric block id and its data value, such as `35:14` for red wool
+pub struct LegacyBlock {
+    pub id: u16,
+    pub data: u8,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LegacySchematic {
+    pub width: u16,
+    pub height: u16,
+    pub length: u16,
+    /// `Alpha` for every schematic of the Java Edition
+    pub materials: String,
+    /// Block ids, see `LegacySchematic::index`
+    pub blocks: Vec<u16>,
+    /// Data values of the blocks, only the lower four bits are used
+    pub data: Vec<u8>,
+    /// Offset from the position the schematic was copied from, written by WorldEdit
+    pub offset: Option<[i32; 3]>,
+    pub entities: Vec<Tag>,
+    pub tile_entities: Vec<Tag>,
+}
+
+impl LegacySchematic {
+    /// Read a schematic file, the compression is detected automatically
+    pub fn read<R: BufRead>(reader: &mut R) -> Result<Self> {
+        let file = read_file(reader)?;
+        LegacySchematic::from_tag(file.root_tag().ok_or_else(|| Error::MissingTag(String::from("Schematic")))?)
+    }
+
+    pub fn from_tag(tag: &Tag) -> Result<Self> {
+        let width = fields::int(tag, "Width")? as u16;
+        let height = fields::int(tag, "Height")? as u16;
+        let length = fields::int(tag, "Length")? as u16;
+        let volume = width as usize * height as usize * length as usize;
+
+        let ids = fields::byte_array(tag, "Blocks")?;
+        let data = fields::byte_array(tag, "Data")?;
+        if ids.len() != volume || data.len() != volume {
+            return Err(fields::invalid("Blocks", "one entry for every block"));
+        }
+        let add = match tag.get("AddBlocks") {
+            Some(..) => NibbleArray::from_bytes(fields::byte_array(tag, "AddBlocks")?.to_vec()),
+            None => NibbleArray::new(0),
+        };
+
+        let blocks = ids.iter().enumerate().map(|(index, &id)| {
+            let upper = if index < add.len() { add.get(index) } else { 0 };
+            u16::from(upper) << 8 | u16::from(id as u8)
+        }).collect();
+
+        let offset = match tag.get("WEOffsetX") {
+            Some(..) => Some([fields::int(tag, "WEOffsetX")?, fields::int(tag, "WEOffsetY")?, fields::int(tag, "WEOffsetZ")?]),
+            None => None,
+        };
+        let list = |name: &str| match tag.get(name) {
+            Some(..) => fields::list(tag, name).map(<[Tag]>::to_vec),
+            None => Ok(Vec::new()),
+        };
+
+        Ok(LegacySchematic {
+            width,
+            height,
+            length,
+            materials: tag.get("Materials").and_then(Tag::as_str).unwrap_or("Alpha").to_owned(),
+            blocks,
+            data: data.iter().map(|&value| value as u8 & 0x0f).collect(),
+            offset,
+            entities: list("Entities")?,
+            tile_entities: list("TileEntities")?,
+        })
+    }
+
+    /// Index of a position within `blocks` and `data`, if it is inside of the schematic
+    pub fn index(&self, x: u16, y: u16, z: u16) -> Option<usize> {
+        if x >= self.width || y >= self.height || z >= self.length {
+            return None;
+        }
+        let (width, length) = (self.width as usize, self.length as usize);
+        Some(x as usize + z as usize * width + y as usize * width * length)
+    }
+
+    pub fn block(&self, x: u16, y: u16, z: u16) -> Option<LegacyBlock> {
+        self.index(x, y, z).map(|index| LegacyBlock { id: self.blocks[index], data: self.data[index] })
+    }
+
+    /// Convert into a Sponge schematic using `mapping` to look up the state of every
+    /// legacy block, failing for blocks it does not know
+    pub fn to_sponge<F>(&self, data_version: i32, mut mapping: F) -> Result<Schematic>
+        where F: FnMut(LegacyBlock) -> Option<BlockState>
+    {
+        let mut schematic = Schematic::new(self.width, self.height, self.length, data_version);
+        schematic.palette.clear();
+        if let Some(offset) = self.offset {
+            schematic.offset = offset;
+        }
+
+        let mut indices = HashMap::new();
+        for (index, (&id, &data)) in self.blocks.iter().zip(&self.data).enumerate() {
+            let block = LegacyBlock { id, data };
+            schematic.blocks[index] = match indices.get(&block) {
+                Some(&palette_index) => palette_index,
+                None => {
+                    let state = mapping(block).ok_or_else(|| Error::InvalidBlockState(format!("{}:{}", id, data)))?;
+                    let palette_index = match schematic.palette.iter().position(|existing| *existing == state) {
+                        Some(palette_index) => palette_index,
+                        None => {
+                            schematic.palette.push(state);
+                            schematic.palette.len() - 1
+                        },
+                    };
+                    indices.insert(block, palette_index);
+                    palette_index
+                },
+            };
+        }
+        if schematic.palette.is_empty() {
+            schematic.palette.push(BlockState::air());
+        }
+
+        for tile_entity in &self.tile_entities {
+            let mut data = tile_entity.clone();
+            for name in &["x", "y", "z", "id"] {
+                data.remove(name);
+            }
+            schematic.block_entities.push(SchematicEntity {
+                pos: [fields::int(tile_entity, "x")?, fields::int(tile_entity, "y")?, fields::int(tile_entity, "z")?],
+                id: fields::string(tile_entity, "id")?.to_owned(),
+                data,
+            });
+        }
+        for entity in &self.entities {
+            let mut data = entity.clone();
+            for name in &["Pos", "id"] {
+                data.remove(name);
+            }
+            schematic.entities.push(SchematicEntity {
+                pos: fields::double_vec3(entity, "Pos")?,
+                id: fields::string(entity, "id")?.to_owned(),
+                data,
+            });
+        }
+        Ok(schematic)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_legacy_schematic() {
+        // A 2x1x2 schematic holding stone, red wool, an id above 255 and a chest
+        let tag = Tag::compound(vec![
+            ("Width", Tag::Short(2)),
+            ("Height", Tag::Short(1)),
+            ("Length", Tag::Short(2)),
+            ("Materials", Tag::from("Alpha")),
+            ("Blocks", Tag::ByteArray(vec![1, 35, 2, 54])),
+            ("Data", Tag::ByteArray(vec![0, 14, 0, 2])),
+            ("AddBlocks", Tag::ByteArray(vec![0x00, 0x01])),
+            ("TileEntities", Tag::List(vec![Tag::compound(vec![
+                ("x", Tag::Int(1)), ("y", Tag::Int(0)), ("z", Tag::Int(1)),
+                ("id", Tag::from("Chest")),
+                ("Items", Tag::List(Vec::new())),
+            ])])),
+        ]);
+        let schematic = LegacySchematic::from_tag(&tag).unwrap();
+        assert_eq!(schematic.block(0, 0, 0), Some(LegacyBlock { id: 1, data: 0 }));
+        assert_eq!(schematic.block(1, 0, 0), Some(LegacyBlock { id: 35, data: 14 }));
+        assert_eq!(schematic.block(0, 0, 1), Some(LegacyBlock { id: 258, data: 0 }));
+        assert_eq!(schematic.block(2, 0, 0), None);
+
+        let mut table = HashMap::new();
+        table.insert(LegacyBlock { id: 1, data: 0 }, BlockState::new("minecraft:stone"));
+        table.insert(LegacyBlock { id: 35, data: 14 }, BlockState::new("minecraft:red_wool"));
+        table.insert(LegacyBlock { id: 258, data: 0 }, BlockState::new("minecraft:stone"));
+        assert!(schematic.to_sponge(1343, |block| table.get(&block).cloned()).is_err());
+
+        let chest = BlockState::new("minecraft:chest").with_property("facing", "north");
+        table.insert(LegacyBlock { id: 54, data: 2 }, chest.clone());
+        let sponge = schematic.to_sponge(1343, |block| table.get(&block).cloned()).unwrap();
+        assert_eq!(sponge.palette.len(), 3);
+        assert_eq!(sponge.block(1, 0, 1), Some(&chest));
+        assert_eq!(sponge.block(0, 0, 1), sponge.block(0, 0, 0));
+        assert_eq!(sponge.block_entities[0].pos, [1, 0, 1]);
+        assert!(sponge.block_entities[0].data.get("Items").is_some());
+    }
+}
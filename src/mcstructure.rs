@@ -0,0 +1,280 @@
+//! Bedrock Edition structure files, the `.mcstructure` files which are uncompressed
+//! little-endian NBT.
+//!
+//! Blocks are stored in layers of indices into the default palette, the first layer
+//! holding the blocks themselves and the second mostly waterlogging. An index of -1
+//! leaves the position untouched when placed. Blocks are ordered by z, then y, then x.
+use std::io::Read;
+use std::io::Write;
+use errors::*;
+
+use fields;
+use read::NbtReader;
+use read::ReadOptions;
+use tag::PocketEndianness;
+use tag::Tag;
+use write::NbtWriter;
+
+pub const FORMAT_VERSION: i32 = 1;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct McStructure {
+    pub format_version: i32,
+    pub size: [i32; 3],
+    /// Position the structure was saved from
+    pub origin: [i32; 3],
+    /// Palette indices of every position for each layer, see `McStructure::index`
+    pub layers: Vec<Vec<i32>>,
+    pub palette: Vec<BedrockBlock>,
+    /// Block entity data and scheduled ticks, by index of the position
+    pub block_position_data: Vec<(usize, Tag)>,
+    pub entities: Vec<Tag>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// A block of the palette, Bedrock block states have values of differing types
+pub struct BedrockBlock {
+    pub name: String,
+    /// Compound of the state values
+    pub states: Tag,
+    pub version: i32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// A position of a structure with its blocks looked up in the palette
+pub struct McBlock<'a> {
+    pub pos: [i32; 3],
+    pub block: &'a BedrockBlock,
+    /// Block of the second layer, such as water in a waterlogged block
+    pub extra: Option<&'a BedrockBlock>,
+    pub data: Option<&'a Tag>,
+}
+
+/// Number of positions within a structure of the given size, or `None` if it overflows
+fn volume(size: [i32; 3]) -> Option<usize> {
+    size.iter().try_fold(1usize, |volume, &axis| volume.checked_mul(axis.max(0) as usize))
+}
+
+impl BedrockBlock {
+    pub fn from_tag(tag: &Tag) -> Result<Self> {
+        Ok(BedrockBlock {
+            name: fields::string(tag, "name")?.to_owned(),
+            states: match tag.get("states") {
+                Some(states @ &Tag::Compound(..)) => states.clone(),
+                Some(..) => return Err(fields::invalid("states", "a Compound")),
+                None => Tag::compound(Vec::<(String, Tag)>::new()),
+            },
+            version: tag.get("version").and_then(Tag::as_i32).unwrap_or(0),
+        })
+    }
+
+    pub fn to_tag(&self) -> Tag {
+        Tag::compound(vec![
+            ("name", Tag::from(self.name.as_str())),
+            ("states", self.states.clone()),
+            ("version", Tag::Int(self.version)),
+        ])
+    }
+}
+
+impl McStructure {
+    /// Create a structure with two layers which leave every position untouched
+    pub fn new(size: [i32; 3]) -> Result<Self> {
+        let volume = volume(size).ok_or_else(|| fields::invalid("size", "a size with a volume that fits in memory"))?;
+        Ok(McStructure {
+            format_version: FORMAT_VERSION,
+            size,
+            origin: [0; 3],
+            layers: vec![vec![-1; volume]; 2],
+            palette: Vec::new(),
+            block_position_data: Vec::new(),
+            entities: Vec::new(),
+        })
+    }
+
+    /// Read a structure file
+    pub fn read<R: Read>(reader: R) -> Result<Self> {
+        let root = NbtReader::<_, PocketEndianness>::with_byte_order(reader, ReadOptions::default()).read_file_root()?;
+        match root {
+            Tag::Compound(ref entries) if !entries.is_empty() => McStructure::from_tag(&entries[0].1),
+            _ => Err(Error::MissingTag(String::from("structure"))),
+        }
+    }
+
+    pub fn write<W: Write>(&self, writer: W) -> Result<()> {
+        NbtWriter::<_, PocketEndianness>::with_byte_order(writer).write_named_tag("", &self.to_tag())
+    }
+
+    pub fn from_tag(tag: &Tag) -> Result<Self> {
+        let size = fields::int_vec3(tag, "size")?;
+        let contents = fields::field(tag, "structure")?;
+
+        // The size is only trusted once it matches the decoded layers
+        let volume = volume(size);
+        let layers: Vec<Vec<i32>> = fields::list(contents, "block_indices")?.iter()
+            .map(|layer| match layer.as_list().map(|indices| indices.iter().map(Tag::as_i32).collect::<Option<Vec<_>>>()) {
+                Some(Some(indices)) if Some(indices.len()) == volume => Ok(indices),
+                _ => Err(fields::invalid("block_indices", "an Int for every position")),
+            })
+            .collect::<Result<_>>()?;
+
+        let palette = fields::field(fields::field(contents, "palette")?, "default")?;
+        let block_palette: Vec<BedrockBlock> = fields::list(palette, "block_palette")?.iter()
+            .map(BedrockBlock::from_tag)
+            .collect::<Result<_>>()?;
+        let palette_len = block_palette.len() as i32;
+        if layers.iter().flat_map(|layer| layer.iter()).any(|&index| index < -1 || index >= palette_len) {
+            return Err(fields::invalid("block_indices", "indices into the palette"));
+        }
+        let mut block_position_data = Vec::new();
+        if let Some(data) = palette.get("block_position_data") {
+            let data = data.entries().ok_or_else(|| fields::invalid("block_position_data", "a Compound"))?;
+            for (index, tag) in data {
+                let index = index.parse().map_err(|_| fields::invalid(index, "a position index"))?;
+                block_position_data.push((index, tag.clone()));
+            }
+        }
+
+        Ok(McStructure {
+            format_version: fields::int(tag, "format_version")?,
+            size,
+            origin: fields::int_vec3(tag, "structure_world_origin")?,
+            layers,
+            palette: block_palette,
+            block_position_data,
+            entities: match contents.get("entities") {
+                Some(..) => fields::list(contents, "entities")?.to_vec(),
+                None => Vec::new(),
+            },
+        })
+    }
+
+    pub fn to_tag(&self) -> Tag {
+        let layers = self.layers.iter()
+            .map(|layer| Tag::List(layer.iter().map(|&index| Tag::Int(index)).collect()))
+            .collect();
+        let position_data = Tag::compound(self.block_position_data.iter()
+            .map(|(index, tag)| (index.to_string(), tag.clone())));
+        let palette = Tag::compound(vec![
+            ("block_palette", Tag::List(self.palette.iter().map(BedrockBlock::to_tag).collect())),
+            ("block_position_data", position_data),
+        ]);
+
+        Tag::compound(vec![
+            ("format_version", Tag::Int(self.format_version)),
+            ("size", fields::int_list(self.size)),
+            ("structure", Tag::compound(vec![
+                ("block_indices", Tag::List(layers)),
+                ("entities", Tag::List(self.entities.clone())),
+                ("palette", Tag::compound(vec![("default", palette)])),
+            ])),
+            ("structure_world_origin", fields::int_list(self.origin)),
+        ])
+    }
+
+    pub fn volume(&self) -> usize {
+        self.size.iter().map(|&axis| axis.max(0) as usize).product()
+    }
+
+    /// Index of a position within each layer, if it is inside of the structure
+    pub fn index(&self, pos: [i32; 3]) -> Option<usize> {
+        if (0..3).any(|axis| pos[axis] < 0 || pos[axis] >= self.size[axis]) {
+            return None;
+        }
+        let (height, length) = (self.size[1] as usize, self.size[2] as usize);
+        Some(pos[0] as usize * height * length + pos[1] as usize * length + pos[2] as usize)
+    }
+
+    fn position(&self, index: usize) -> [i32; 3] {
+        let (height, length) = (self.size[1] as usize, self.size[2] as usize);
+        [(index / (height * length)) as i32, (index / length % height) as i32, (index % length) as i32]
+    }
+
+    /// Set the block of the first layer at a position, adding it to the palette when needed
+    ///
+    /// # Panics
+    ///
+    /// Panics if the position is outside of the structure
+    pub fn set_block(&mut self, pos: [i32; 3], block: BedrockBlock) {
+        let index = self.index(pos).expect("position outside of the structure");
+        let palette_index = match self.palette.iter().position(|existing| *existing == block) {
+            Some(palette_index) => palette_index,
+            None => {
+                self.palette.push(block);
+                self.palette.len() - 1
+            },
+        };
+        if self.layers.is_empty() {
+            self.layers.push(vec![-1; self.volume()]);
+        }
+        self.layers[0][index] = palette_index as i32;
+    }
+
+    /// Positions of the first layer which hold a block, with the blocks looked up
+    pub fn blocks<'a>(&'a self) -> impl Iterator<Item = McBlock<'a>> + 'a {
+        let lookup = move |index: i32| match index {
+            -1 => None,
+            index => Some(&self.palette[index as usize]),
+        };
+        self.layers.first().into_iter()
+            .flat_map(|layer| layer.iter().enumerate())
+            .filter_map(move |(index, &block)| lookup(block).map(|block| McBlock {
+                pos: self.position(index),
+                block,
+                extra: self.layers.get(1).and_then(|layer| lookup(layer[index])),
+                data: self.block_position_data.iter()
+                    .find(|&&(data_index, _)| data_index == index)
+                    .map(|(_, tag)| tag),
+            }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_mcstructure() {
+        let mut structure = McStructure::new([2, 3, 4]).unwrap();
+        let stone = BedrockBlock {
+            name: String::from("minecraft:stone"),
+            states: Tag::compound(vec![("stone_type", Tag::from("granite"))]),
+            version: 17959425,
+        };
+        let chest = BedrockBlock {
+            name: String::from("minecraft:chest"),
+            states: Tag::compound(vec![("facing_direction", Tag::Int(2))]),
+            version: 17959425,
+        };
+        structure.set_block([0, 0, 0], stone.clone());
+        structure.set_block([1, 2, 3], chest.clone());
+        let index = structure.index([1, 2, 3]).unwrap();
+        assert_eq!(index, 23);
+        structure.block_position_data.push((index, Tag::compound(vec![
+            ("block_entity_data", Tag::compound(vec![("id", Tag::from("Chest"))])),
+        ])));
+
+        let mut written = Vec::new();
+        structure.write(&mut written).unwrap();
+        // Little-endian, so the length of the "format_version" name starts with its low byte
+        assert_eq!(&written[..6], &[0x0a, 0x00, 0x00, 0x03, 0x0e, 0x00]);
+        let reread = McStructure::read(Cursor::new(written)).unwrap();
+        assert_eq!(reread, structure);
+
+        let blocks: Vec<_> = reread.blocks().collect();
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(blocks[0].block, &stone);
+        assert_eq!((blocks[1].pos, blocks[1].block), ([1, 2, 3], &chest));
+        assert!(blocks[1].data.is_some());
+        assert!(blocks[1].extra.is_none());
+
+        // The size must match the layers before anything is allocated for it
+        let mut tag = structure.to_tag();
+        tag.insert("size", fields::int_list([i32::MAX, i32::MAX, i32::MAX]));
+        assert!(McStructure::from_tag(&tag).is_err());
+        tag.insert("size", fields::int_list([1 << 20, 1 << 20, 1]));
+        assert!(McStructure::from_tag(&tag).is_err());
+        assert!(McStructure::new([i32::MAX, i32::MAX, i32::MAX]).is_err());
+    }
+}
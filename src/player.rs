@@ -0,0 +1,244 @@
+//! The data of a player, as stored in `playerdata/<uuid>.dat` and in the `Player`
+//! Compound of a singleplayer `level.dat`.
+use std::convert::TryFrom;
+use std::io::BufRead;
+use std::io::Write;
+use std::ops::Range;
+use errors::*;
+
+use fields;
+use item::Inventory;
+use item::ItemStack;
+use read::read_file;
+use read::NBTFile;
+use tag::Compression;
+use tag::Tag;
+use uuid::Uuid;
+use world::Dimension;
+use write::write_file;
+
+/// First data version storing the dimension as a namespaced id, 20w21a
+pub const DIMENSION_ID_DATA_VERSION: i32 = 2554;
+
+/// Slots of the hotbar within `PlayerData::inventory`
+pub const HOTBAR_SLOTS: Range<i8> = 0..9;
+/// Slots of the hotbar and the rest of the main inventory
+pub const MAIN_SLOTS: Range<i8> = 0..36;
+/// Slots of the boots, leggings, chestplate and helmet
+pub const ARMOR_SLOTS: Range<i8> = 100..104;
+pub const OFFHAND_SLOT: i8 = -106;
+
+/// Entries of a player which are read and written by `PlayerData` itself
+const KNOWN: &[&str] = &[
+    "DataVersion", "UUID", "Pos", "Rotation", "Dimension", "Health", "XpLevel", "XpP",
+    "XpTotal", "SelectedItemSlot", "Inventory", "EnderItems", "active_effects", "ActiveEffects",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlayerData {
+    /// Only written since 1.9
+    pub data_version: Option<i32>,
+    /// Written as four Ints since 1.16
+    pub uuid: Option<Uuid>,
+    pub pos: [f64; 3],
+    /// Yaw and pitch in degrees
+    pub rotation: [f32; 2],
+    pub dimension: Option<Dimension>,
+    pub health: f32,
+    pub xp_level: i32,
+    /// Progress towards the next level, between 0 and 1
+    pub xp_progress: f32,
+    pub xp_total: i32,
+    /// Hotbar slot held in the main hand
+    pub selected_slot: i32,
+    pub inventory: Inventory,
+    pub ender_items: Inventory,
+    /// Active status effects, named `active_effects` since 1.20.2 and `ActiveEffects` before.
+    /// When a player has both, only `active_effects` is read, as by the game, and
+    /// `ActiveEffects` is kept in `other`.
+    pub effects: Vec<Tag>,
+    /// Every other entry of the player, such as `foodLevel` or `abilities`
+    pub other: Vec<(String, Tag)>,
+}
+
+impl PlayerData {
+    /// Read a player file, the compression is detected automatically
+    pub fn read<R: BufRead>(reader: &mut R) -> Result<Self> {
+        let file = read_file(reader)?;
+        PlayerData::from_tag(file.root_tag().ok_or_else(|| Error::MissingTag(String::from("player")))?)
+    }
+
+    /// Write a gzip compressed player file, as Minecraft does
+    pub fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+        write_file(writer, &NBTFile::with_root(Compression::Gzip, self.to_tag()))
+    }
+
+    pub fn from_tag(tag: &Tag) -> Result<Self> {
+        let uuid = match tag.get("UUID").map(Tag::as_uuid) {
+            Some(Some(uuid)) => Some(uuid),
+            Some(None) => return Err(fields::invalid("UUID", "four Ints")),
+            None => None,
+        };
+        let rotation: Option<Option<Vec<f64>>> = tag.get("Rotation")
+            .map(|rotation| rotation.as_list().and_then(|angles| angles.iter().map(Tag::as_f64).collect()));
+        let rotation = match rotation {
+            Some(Some(ref angles)) if angles.len() == 2 => [angles[0] as f32, angles[1] as f32],
+            Some(..) => return Err(fields::invalid("Rotation", "two Floats")),
+            None => [0.0; 2],
+        };
+        let dimension = match tag.get("Dimension") {
+            Some(dimension) => Some(match dimension.as_str() {
+                Some(id) => Dimension::from_id(id),
+                None => dimension.as_i32()
+                    .and_then(Dimension::from_legacy)
+                    .ok_or_else(|| fields::invalid("Dimension", "a dimension"))?,
+            }),
+            None => None,
+        };
+        let inventory = |name: &str| match tag.get(name) {
+            Some(items) => Inventory::from_tag(items),
+            None => Ok(Inventory::default()),
+        };
+        let mut other = fields::others(tag, KNOWN);
+        let effects = match (tag.get("active_effects"), tag.get("ActiveEffects")) {
+            (Some(..), Some(legacy)) => {
+                other.push((String::from("ActiveEffects"), legacy.clone()));
+                fields::list(tag, "active_effects")?.to_vec()
+            },
+            (Some(..), None) => fields::list(tag, "active_effects")?.to_vec(),
+            (None, Some(..)) => fields::list(tag, "ActiveEffects")?.to_vec(),
+            (None, None) => Vec::new(),
+        };
+
+        Ok(PlayerData {
+            data_version: fields::optional(tag, "DataVersion", fields::int)?,
+            uuid,
+            pos: fields::double_vec3(tag, "Pos")?,
+            rotation,
+            dimension,
+            health: fields::optional(tag, "Health", fields::float)?.unwrap_or(20.0),
+            xp_level: fields::optional(tag, "XpLevel", fields::int)?.unwrap_or(0),
+            xp_progress: fields::optional(tag, "XpP", fields::float)?.unwrap_or(0.0),
+            xp_total: fields::optional(tag, "XpTotal", fields::int)?.unwrap_or(0),
+            selected_slot: fields::optional(tag, "SelectedItemSlot", fields::int)?.unwrap_or(0),
+            inventory: inventory("Inventory")?,
+            ender_items: inventory("EnderItems")?,
+            effects,
+            other,
+        })
+    }
+
+    pub fn to_tag(&self) -> Tag {
+        let mut tag = Tag::compound(Vec::<(String, Tag)>::new());
+        if let Some(data_version) = self.data_version {
+            tag.insert("DataVersion", Tag::Int(data_version));
+        }
+        if let Some(uuid) = self.uuid {
+            tag.insert("UUID", uuid.to_tag());
+        }
+        tag.insert("Pos", fields::double_list(self.pos));
+        tag.insert("Rotation", Tag::List(self.rotation.iter().map(|&angle| Tag::Float(angle)).collect()));
+        if let Some(ref dimension) = self.dimension {
+            let legacy = match self.data_version {
+                Some(version) => version < DIMENSION_ID_DATA_VERSION,
+                None => true,
+            };
+            tag.insert("Dimension", match dimension.legacy() {
+                Some(number) if legacy => Tag::Int(number),
+                _ => Tag::from(dimension.id()),
+            });
+        }
+        tag.insert("Health", Tag::Float(self.health));
+        tag.insert("XpLevel", Tag::Int(self.xp_level));
+        tag.insert("XpP", Tag::Float(self.xp_progress));
+        tag.insert("XpTotal", Tag::Int(self.xp_total));
+        tag.insert("SelectedItemSlot", Tag::Int(self.selected_slot));
+        tag.insert("Inventory", self.inventory.to_tag());
+        tag.insert("EnderItems", self.ender_items.to_tag());
+        // Effects are only written when there are any, their format tells which name to use
+        if let Some(effect) = self.effects.first() {
+            let name = if effect.get("Id").is_some() { "ActiveEffects" } else { "active_effects" };
+            tag.insert(name, Tag::List(self.effects.clone()));
+        }
+        for (name, other) in &self.other {
+            tag.insert(name.as_str(), other.clone());
+        }
+        tag
+    }
+
+    /// The item held in the main hand
+    pub fn selected_item(&self) -> Option<&ItemStack> {
+        self.inventory.get(i8::try_from(self.selected_slot).ok()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_player_data() {
+        let tag = Tag::compound(vec![
+            ("DataVersion", Tag::Int(3700)),
+            ("UUID", Tag::IntArray(vec![1, 2, 3, 4])),
+            ("Pos", fields::double_list([0.5, 64.0, -10.5])),
+            ("Rotation", Tag::List(vec![Tag::Float(90.0), Tag::Float(0.0)])),
+            ("Dimension", Tag::from("minecraft:the_nether")),
+            ("Health", Tag::Float(18.5)),
+            ("XpLevel", Tag::Int(30)),
+            ("SelectedItemSlot", Tag::Int(2)),
+            ("Inventory", Tag::List(vec![Tag::compound(vec![
+                ("id", Tag::from("minecraft:torch")),
+                ("Count", Tag::Byte(32)),
+                ("Slot", Tag::Byte(2)),
+            ])])),
+            ("active_effects", Tag::List(vec![Tag::compound(vec![("id", Tag::from("minecraft:speed"))])])),
+            ("foodLevel", Tag::Int(20)),
+        ]);
+        let mut player = PlayerData::from_tag(&tag).unwrap();
+        assert_eq!(player.dimension, Some(Dimension::Nether));
+        assert_eq!(player.uuid.map(|uuid| uuid.to_string()).as_deref(), Some("00000001-0000-0002-0000-000300000004"));
+        assert_eq!(player.selected_item().map(|item| item.count), Some(32));
+        // A slot which does not fit into a Byte must not alias slot 2
+        let mut aliased = player.clone();
+        aliased.selected_slot = 258;
+        assert_eq!(aliased.selected_item(), None);
+        let mut invalid = tag.clone();
+        invalid.insert("Inventory", Tag::List(vec![Tag::compound(vec![
+            ("id", Tag::from("minecraft:torch")),
+            ("Count", Tag::Byte(1)),
+            ("Slot", Tag::Int(258)),
+        ])]));
+        assert!(PlayerData::from_tag(&invalid).is_err());
+        assert_eq!(player.other, vec![(String::from("foodLevel"), Tag::Int(20))]);
+
+        player.inventory.set(OFFHAND_SLOT, ItemStack::new("minecraft:shield", 1));
+        assert_eq!(player.inventory.first_empty(HOTBAR_SLOTS), Some(0));
+        let mut written = Vec::new();
+        player.write(&mut written).unwrap();
+        let reread = PlayerData::read(&mut Cursor::new(written)).unwrap();
+        assert_eq!(reread, player);
+        assert_eq!(reread.to_tag().get("active_effects").and_then(Tag::as_list).map(<[Tag]>::len), Some(1));
+
+        // Before 1.16 the dimension was a number
+        let mut legacy = tag;
+        legacy.insert("DataVersion", Tag::Int(1343));
+        legacy.insert("Dimension", Tag::Int(-1));
+        let player = PlayerData::from_tag(&legacy).unwrap();
+        assert_eq!(player.dimension, Some(Dimension::Nether));
+        assert_eq!(player.to_tag().get("Dimension"), Some(&Tag::Int(-1)));
+
+        // Stale effects of the old name next to the current ones are kept as they are
+        let stale = Tag::List(vec![Tag::compound(vec![("Id", Tag::Byte(1))])]);
+        let mut both = legacy;
+        both.insert("active_effects", Tag::List(vec![Tag::compound(vec![("id", Tag::from("minecraft:haste"))])]));
+        both.insert("ActiveEffects", stale.clone());
+        let player = PlayerData::from_tag(&both).unwrap();
+        assert_eq!(player.effects[0].get("id"), Some(&Tag::from("minecraft:haste")));
+        assert!(player.other.contains(&(String::from("ActiveEffects"), stale.clone())));
+        let written = player.to_tag();
+        assert_eq!(written.get("ActiveEffects"), Some(&stale));
+        assert_eq!(PlayerData::from_tag(&written).unwrap(), player);
+    }
+}
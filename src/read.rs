@@ -1,210 +1,960 @@
+use std::cmp;
+use std::fmt;
 use std::io;
 use std::io::Read;
 use std::io::BufRead;
+use std::mem;
+use std::marker::PhantomData;
+use byteorder::ByteOrder;
+use byteorder::LittleEndian;
 use byteorder::ReadBytesExt;
 use errors::*;
 
 use tag::Tag;
 use tag::Compression;
+use tag::DETECT_LENGTH;
 use tag::DesktopEndianness;
+use tag::PocketEndianness;
+
+/// Nesting limit enforced by the vanilla server
+pub const DEFAULT_MAX_DEPTH: usize = 512;
+
+/// Quota applied by the vanilla server to NBT data received over the network
+pub const NETWORK_MAX_BYTES: u64 = 2 * 1024 * 1024;
+
+/// Upper bound on the number of elements reserved up-front for a collection,
+/// anything beyond this is only allocated once it has actually been read
+const MAX_PREALLOCATION: usize = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// How a reader reacts to input which is damaged. Whatever the mode, exceeding one
+/// of the limits is always an error.
+pub enum ReadMode {
+    /// Fail on the first problem found
+    Strict,
+    /// When the input ends early or is followed by unexpected data, keep everything
+    /// read up to that point and record the problem as a `Diagnostic`
+    Lenient,
+    /// Like `Lenient`, but also salvage what was read before an invalid tag type,
+    /// length or string. The container holding the offending tag is cut short and
+    /// reading stops, as the remaining input can no longer be interpreted.
+    Recover,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// Options controlling how data is read, including the limits protecting against
+/// hostile or corrupted input
+pub struct ReadOptions {
+    /// Maximum number of nested List and Compound tags
+    pub max_depth: usize,
+    /// Maximum number of bytes the parsed tags may account for in memory,
+    /// similar to the quota of the vanilla `NbtAccounter`
+    pub max_bytes: u64,
+    /// Maximum number of elements in a single List or array tag
+    pub max_length: usize,
+    pub mode: ReadMode,
+}
+
+impl ReadOptions {
+    /// Limits suitable for NBT data received from untrusted clients
+    pub fn network() -> Self {
+        ReadOptions {
+            max_bytes: NETWORK_MAX_BYTES,
+            ..ReadOptions::default()
+        }
+    }
+
+    /// Default limits, keeping as much of damaged input as possible
+    pub fn lenient() -> Self {
+        ReadOptions {
+            mode: ReadMode::Lenient,
+            ..ReadOptions::default()
+        }
+    }
+
+    /// Default limits, salvaging as much of corrupted input as possible
+    pub fn recover() -> Self {
+        ReadOptions {
+            mode: ReadMode::Recover,
+            ..ReadOptions::default()
+        }
+    }
+}
+
+impl Default for ReadOptions {
+    fn default() -> Self {
+        ReadOptions {
+            max_depth: DEFAULT_MAX_DEPTH,
+            max_bytes: u64::MAX,
+            max_length: i32::MAX as usize,
+            mode: ReadMode::Strict,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// The limit that was exceeded when reading was aborted
+pub enum Limit {
+    Depth,
+    Bytes,
+    Length,
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Limit::Depth => "nesting depth",
+            Limit::Bytes => "byte quota",
+            Limit::Length => "collection length",
+        })
+    }
+}
+
+#[derive(Debug)]
+/// A problem which was recovered from while reading in `ReadMode::Lenient` or
+/// `ReadMode::Recover`
+pub struct Diagnostic {
+    /// Byte offset of the tag which could not be read
+    pub offset: u64,
+    /// Path to the tag which could not be read, it is missing from the result
+    pub path: TagPath,
+    pub error: Error,
+}
+
+impl Diagnostic {
+    fn new(error: Error, offset: u64) -> Self {
+        match error {
+            Error::Position { offset, path, source } => Diagnostic { offset, path, error: *source },
+            error => Diagnostic { offset, path: TagPath::default(), error },
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} (tag '{}' at byte offset {})", self.error, self.path, self.offset)
+    }
+}
 
 #[derive(Debug)]
 /// Structure representing a NBT file that has been fully parsed
 pub struct NBTFile {
     pub compression: Compression,
-    pub root: Tag
+    /// Storage version from the header of a Bedrock `level.dat`, such files are
+    /// little-endian and are never compressed
+    pub storage_version: Option<i32>,
+    /// Compound holding the named root tag of the file
+    pub root: Tag,
+    /// Problems recovered from when not reading in `ReadMode::Strict`
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl NBTFile {
+    pub fn new(compression: Compression, root: Tag) -> Self {
+        NBTFile {
+            compression,
+            storage_version: None,
+            root,
+            diagnostics: Vec::new(),
+        }
+    }
+
+    /// Create a file holding a single root tag with an empty name
+    pub fn with_root(compression: Compression, tag: Tag) -> Self {
+        NBTFile::new(compression, Tag::Compound(vec![(String::new(), tag)]))
+    }
+
+    /// The first root tag of the file, regardless of its name
+    pub fn root_tag(&self) -> Option<&Tag> {
+        match self.root {
+            Tag::Compound(ref entries) => entries.first().map(|(_, tag)| tag),
+            _ => None,
+        }
+    }
 }
 
 pub fn read_file<R: BufRead>(reader: &mut R) -> Result<NBTFile> {
-    let compression_header = if let Ok(buf) = reader.fill_buf() {
-        buf[0]
-    } else {
-        bail!("Failed to peek Compression type")
-    };
+    read_file_with_options(reader, ReadOptions::default())
+}
+
+pub fn read_file_with_options<R: BufRead>(reader: &mut R, options: ReadOptions) -> Result<NBTFile> {
+    let mut header = [0u8; DETECT_LENGTH];
+    let length = read_header(reader, &mut header)?;
+    if let Some((storage_version, payload_length)) = bedrock_header(&header[..length]) {
+        let reader = io::Cursor::new(&header[BEDROCK_HEADER_LENGTH..length]).chain(reader);
+        return read_bedrock_payload(reader, storage_version, payload_length, options);
+    }
+    let compression = Compression::detect(&header[..length])?;
 
-    let compression = Compression::from_byte(compression_header).unwrap();
-    let root = match compression {
-        Compression::Zlib => read_compound(&mut ::flate2::read::ZlibDecoder::new(reader)),
-        Compression::Gzip => read_compound(&mut ::flate2::read::GzDecoder::new(reader)),
-        Compression::None => read_compound(&mut ::flate2::read::GzDecoder::new(reader)),
-    }?;
+    let reader = io::Cursor::new(&header[..length]).chain(reader);
+    let mut reader = NbtReader::with_options(decompress(reader, &compression), options);
+    let root = reader.read_file_root()?;
 
     Ok(NBTFile {
         compression,
-        root
+        storage_version: None,
+        root,
+        diagnostics: reader.take_diagnostics(),
     })
 }
 
-pub fn read_compound<R: Read>(reader: &mut R) -> Result<Tag> {
-    let mut container = Vec::new();
-
-    loop {
-        let tag_type = {
-            let mut buf: [u8; 1] = [0];
-            if let Err(e) = reader.read_exact(&mut buf) {
-                if e.kind() == io::ErrorKind::UnexpectedEof {
-                    break;
-                }
-                bail!(e)
+/// Size of the storage version and payload length preceding a Bedrock `level.dat`
+pub const BEDROCK_HEADER_LENGTH: usize = 8;
+
+/// Parse the storage version and payload length of a Bedrock `level.dat`, which
+/// are only recognised when directly followed by a Compound tag
+fn bedrock_header(header: &[u8]) -> Option<(i32, u32)> {
+    match *header {
+        // A raw Java compound cannot start with three zero bytes unless it is empty
+        [_, 0x00, 0x00, 0x00, _, _, _, _, 0x0a, ..] => Some((
+            LittleEndian::read_i32(&header[0..4]),
+            LittleEndian::read_u32(&header[4..BEDROCK_HEADER_LENGTH]),
+        )),
+        _ => None,
+    }
+}
+
+fn read_bedrock_payload<R: Read>(reader: R, storage_version: i32, length: u32, options: ReadOptions) -> Result<NBTFile> {
+    let mut reader = NbtReader::<_, PocketEndianness>::with_byte_order(reader.take(u64::from(length)), options);
+    let root = reader.read_file_root()?;
+    if reader.diagnostics().is_empty() && reader.offset() != u64::from(length) {
+        let offset = reader.offset();
+        reader.recover(Error::LengthMismatch { expected: u64::from(length), read: offset }.at(offset, TagPath::default()))?;
+    }
+    // Bytes beyond the length given by the header are rejected like those following
+    // the root tag of any other file
+    let mut buf: [u8; 1] = [0];
+    if reader.diagnostics().is_empty() && reader.inner.inner.get_mut().read(&mut buf)? != 0 {
+        reader.recover(Error::TrailingData.at(u64::from(length), TagPath::default()))?;
+    }
+
+    Ok(NBTFile {
+        compression: Compression::None,
+        storage_version: Some(storage_version),
+        root,
+        diagnostics: reader.take_diagnostics(),
+    })
+}
+
+/// Fill `header` with as many bytes as are available, stopping early only at the end of the input
+fn read_header<R: Read>(reader: &mut R, header: &mut [u8]) -> Result<usize> {
+    let mut length = 0;
+    while length < header.len() {
+        match reader.read(&mut header[length..]) {
+            Ok(0) => break,
+            Ok(read) => length += read,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {},
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(length)
+}
+
+/// Wrap `reader` with a decoder for the given compression format
+pub fn decompress<'a, R: Read + 'a>(reader: R, compression: &Compression) -> Box<dyn Read + 'a> {
+    match *compression {
+        Compression::None => Box::new(reader),
+        Compression::Gzip => Box::new(::flate2::read::GzDecoder::new(reader)),
+        Compression::Zlib => Box::new(::flate2::read::ZlibDecoder::new(reader)),
+        Compression::Lz4 => Box::new(::lz4::Lz4BlockReader::new(reader)),
+        Compression::Lz4Frame => Box::new(::lz4_flex::frame::FrameDecoder::new(reader)),
+    }
+}
+
+/// Reads tags from an underlying reader while enforcing a set of `ReadOptions`.
+///
+/// The free `read_*` functions are shorthands for a reader using the default options.
+/// Any error is reported together with the byte offset and `TagPath` of the tag
+/// that could not be read, see `Error::Position`.
+///
+/// Java Edition data is big-endian, Bedrock Edition data can be read using a reader
+/// created by `NbtReader::<_, PocketEndianness>::with_byte_order`.
+pub struct NbtReader<R, E = DesktopEndianness> {
+    inner: Counter<R>,
+    options: ReadOptions,
+    depth: usize,
+    accounted: u64,
+    diagnostics: Vec<Diagnostic>,
+    byte_order: PhantomData<E>,
+}
+
+impl<R: Read> NbtReader<R> {
+    pub fn new(inner: R) -> Self {
+        NbtReader::with_options(inner, ReadOptions::default())
+    }
+
+    pub fn with_options(inner: R, options: ReadOptions) -> Self {
+        NbtReader::with_byte_order(inner, options)
+    }
+}
+
+impl<R: Read, E: ByteOrder> NbtReader<R, E> {
+    pub fn with_byte_order(inner: R, options: ReadOptions) -> Self {
+        NbtReader {
+            inner: Counter { inner, offset: 0 },
+            options,
+            depth: 0,
+            accounted: 0,
+            diagnostics: Vec::new(),
+            byte_order: PhantomData,
+        }
+    }
+
+    /// Number of bytes accounted against `ReadOptions::max_bytes` so far
+    pub fn accounted(&self) -> u64 {
+        self.accounted
+    }
+
+    /// Number of bytes consumed from the underlying reader so far
+    pub fn offset(&self) -> u64 {
+        self.inner.offset
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner.inner
+    }
+
+    /// Problems recovered from so far when not reading in `ReadMode::Strict`
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    pub fn take_diagnostics(&mut self) -> Vec<Diagnostic> {
+        mem::take(&mut self.diagnostics)
+    }
+
+    /// Record `error` as a diagnostic if the `ReadMode` allows it, otherwise return it
+    fn recover(&mut self, error: Error) -> Result<()> {
+        if !is_recoverable(error.inner(), self.options.mode) {
+            return Err(error);
+        }
+        let offset = self.offset();
+        self.diagnostics.push(Diagnostic::new(error, offset));
+        Ok(())
+    }
+
+    /// Read the single named root tag of a file, which must make up the entire input.
+    /// Like `NBTFile::root`, the result is a Compound holding the named root tag.
+    pub fn read_file_root(&mut self) -> Result<Tag> {
+        let start = self.offset();
+        let root = match self.read_root_tag()? {
+            Some(root) => vec![root],
+            None if !self.diagnostics.is_empty() => Vec::new(),
+            None => {
+                let error = Error::from(io::Error::from(io::ErrorKind::UnexpectedEof));
+                self.recover(error.at(start, TagPath::default()))?;
+                Vec::new()
+            },
+        };
+
+        // Once a problem has been recovered from, whatever follows cannot be trusted
+        let end = self.offset();
+        let mut buf: [u8; 1] = [0];
+        if self.diagnostics.is_empty() && self.inner.read(&mut buf)? != 0 {
+            self.recover(Error::TrailingData.at(end, TagPath::default()))?;
+        }
+        Ok(Tag::Compound(root))
+    }
+
+    /// Charge `bytes` against the byte quota, this must happen *before* allocating
+    fn account(&mut self, bytes: u64) -> Result<()> {
+        self.accounted = self.accounted.saturating_add(bytes);
+        if self.accounted > self.options.max_bytes {
+            return Err(Error::LimitExceeded { limit: Limit::Bytes, max: self.options.max_bytes })
+        }
+        Ok(())
+    }
+
+    /// Validate the length prefix of a collection and charge its elements
+    fn account_length(&mut self, length: i32, element_size: usize) -> Result<usize> {
+        if length < 0 {
+            return Err(Error::InvalidLength(length))
+        }
+        let length = length as usize;
+        if length > self.options.max_length {
+            return Err(Error::LimitExceeded { limit: Limit::Length, max: self.options.max_length as u64 })
+        }
+        self.account(length as u64 * element_size as u64)?;
+        Ok(length)
+    }
+
+    fn enter(&mut self) -> Result<()> {
+        if self.depth >= self.options.max_depth {
+            return Err(Error::LimitExceeded { limit: Limit::Depth, max: self.options.max_depth as u64 })
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    fn leave(&mut self) {
+        self.depth -= 1;
+    }
+
+    /// Read a tag of the given type which is not nested inside of another tag
+    fn read_value(&mut self, tag_type: u8) -> Result<Tag> {
+        if tag_type == 0x09 || tag_type == 0x0A {
+            return self.read_nested(tag_type);
+        }
+
+        let start = self.offset();
+        self.read_payload(tag_type)
+            .map_err(|e| e.at(start, TagPath::default()))
+    }
+
+    #[inline]
+    pub fn read_byte(&mut self) -> Result<Tag> {
+        self.read_value(0x01)
+    }
+
+    #[inline]
+    pub fn read_short(&mut self) -> Result<Tag> {
+        self.read_value(0x02)
+    }
+
+    #[inline]
+    pub fn read_int(&mut self) -> Result<Tag> {
+        self.read_value(0x03)
+    }
+
+    #[inline]
+    pub fn read_long(&mut self) -> Result<Tag> {
+        self.read_value(0x04)
+    }
+
+    #[inline]
+    pub fn read_float(&mut self) -> Result<Tag> {
+        self.read_value(0x05)
+    }
+
+    #[inline]
+    pub fn read_double(&mut self) -> Result<Tag> {
+        self.read_value(0x06)
+    }
+
+    pub fn read_byte_array(&mut self) -> Result<Tag> {
+        self.read_value(0x07)
+    }
+
+    pub fn read_string(&mut self) -> Result<Tag> {
+        self.read_value(0x08)
+    }
+
+    pub fn read_list(&mut self) -> Result<Tag> {
+        self.read_value(0x09)
+    }
+
+    pub fn read_compound(&mut self) -> Result<Tag> {
+        self.read_value(0x0A)
+    }
+
+    pub fn read_int_array(&mut self) -> Result<Tag> {
+        self.read_value(0x0B)
+    }
+
+    pub fn read_long_array(&mut self) -> Result<Tag> {
+        self.read_value(0x0C)
+    }
+
+    /// Read the payload of any tag which does not contain other tags
+    fn read_payload(&mut self, tag_type: u8) -> Result<Tag> {
+        self.account(mem::size_of::<Tag>() as u64)?;
+        Ok(match tag_type {
+            0x01 => Tag::Byte(self.inner.read_i8()?),
+            0x02 => Tag::Short(self.inner.read_i16::<E>()?),
+            0x03 => Tag::Int(self.inner.read_i32::<E>()?),
+            0x04 => Tag::Long(self.inner.read_i64::<E>()?),
+            0x05 => Tag::Float(self.inner.read_f32::<E>()?),
+            0x06 => Tag::Double(self.inner.read_f64::<E>()?),
+            0x07 => self.read_byte_array_payload()?,
+            0x08 => Tag::String(self.read_string_value()?),
+            0x0B => self.read_int_array_payload()?,
+            0x0C => self.read_long_array_payload()?,
+            data_type => return Err(Error::InvalidTagType(data_type))
+        })
+    }
+
+    /// Read a single named tag as found at the root of a file, returning `None` if the
+    /// input ends cleanly before it. Ending anywhere within the tag is an error.
+    pub fn read_root_tag(&mut self) -> Result<Option<(String, Tag)>> {
+        let start = self.offset();
+        let mut buf: [u8; 1] = [0];
+        loop {
+            match self.inner.read(&mut buf) {
+                Ok(0) => return Ok(None),
+                Ok(..) => break,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {},
+                Err(e) => return Err(Error::from(e).at(start, TagPath::default())),
             }
-            buf[0]
+        }
+        if buf[0] == 0x00 {
+            return Ok(Some((String::new(), Tag::End)));
+        }
+
+        let name = match self.read_string_value() {
+            Ok(name) => name,
+            Err(e) => return self.recover(e.at(start, TagPath::default())).map(|_| None),
         };
+        let tag = match buf[0] {
+            tag_type @ 0x09 | tag_type @ 0x0A => self.read_nested(tag_type)?,
+            tag_type => match self.read_payload(tag_type) {
+                Ok(tag) => tag,
+                Err(e) => {
+                    let path = TagPath(vec![PathSegment::Name(name)]);
+                    return self.recover(e.at(start, path)).map(|_| None);
+                },
+            },
+        };
+        Ok(Some((name, tag)))
+    }
+
+    /// Iterate over root tags written back to back, as found in Bedrock LevelDB values
+    /// or captured network traffic, until the input ends cleanly.
+    ///
+    /// Unlike `read_file`, data following a root tag is never treated as an error.
+    pub fn root_tags(self) -> RootTags<R, E> {
+        RootTags { reader: self, done: false }
+    }
+
+    /// Read a List or Compound using an explicit stack rather than recursion,
+    /// so that the nesting depth is bounded by `ReadOptions::max_depth` alone
+    /// and never by the size of the call stack.
+    ///
+    /// When a tag cannot be read and the `ReadMode` allows recovering, it is left out
+    /// and every enclosing frame is returned with the tags read up to that point.
+    fn read_nested(&mut self, tag_type: u8) -> Result<Tag> {
+        let depth = self.depth;
+        let mut stack = Vec::new();
+        let mut cursor = Cursor { start: self.offset(), child: None };
+
+        match self.open_frame(tag_type) {
+            Ok(frame) => stack.push(frame),
+            Err(e) => {
+                self.depth = depth;
+                self.recover(e.at(cursor.start, TagPath::default()))?;
+                return Ok(if tag_type == 0x0A { Tag::Compound(Vec::new()) } else { Tag::List(Vec::new()) });
+            },
+        }
 
-        if tag_type == 0x00 {
-            container.push((String::with_capacity(0), Tag::End));
-            break;
+        loop {
+            match self.read_next(&mut stack, &mut cursor) {
+                Ok(Some(tag)) => return Ok(tag),
+                Ok(None) => {},
+                Err(e) => {
+                    self.depth = depth;
+                    let path = TagPath::from_frames(&stack, cursor.child.as_ref().map(|name| name.as_ref().map(String::as_str)));
+                    self.recover(e.at(cursor.start, path))?;
+                    return Ok(close_frames(stack));
+                },
+            }
         }
+    }
+
+    /// Advance by a single tag within the innermost frame, returning the outermost
+    /// tag once every frame has been completed
+    fn read_next(&mut self, stack: &mut Vec<Frame>, cursor: &mut Cursor) -> Result<Option<Tag>> {
+        cursor.start = self.offset();
+        cursor.child = None;
+
+        let next = match stack.last_mut() {
+            Some(frame) => self.next_child(frame)?,
+            None => unreachable!("the stack is only emptied by returning"),
+        };
 
-        let tag_name = match read_string(reader)? {
-            Tag::String(tag_name) => tag_name,
-            _ => bail!("failed to read compound tag name")
+        let (name, tag) = match next {
+            Some((tag_type, name)) => {
+                cursor.child = Some(name);
+                if tag_type == 0x09 || tag_type == 0x0A {
+                    let mut frame = self.open_frame(tag_type)?;
+                    frame.set_name(cursor.child.take().and_then(|name| name));
+                    stack.push(frame);
+                    return Ok(None);
+                }
+                let tag = self.read_payload(tag_type)?;
+                (cursor.child.take().and_then(|name| name), tag)
+            },
+            None => {
+                let frame = stack.pop().expect("stack cannot be empty");
+                self.leave();
+                frame.close()
+            },
         };
 
-        let tag_value = match tag_type {
-            0x01 => read_byte(reader),
-            0x02 => read_short(reader),
-            0x03 => read_int(reader),
-            0x04 => read_long(reader),
-            0x05 => read_float(reader),
-            0x06 => read_double(reader),
-            0x07 => read_byte_array(reader),
-            0x08 => read_string(reader),
-            0x09 => read_list(reader),
-            0x0A => read_compound(reader),
-            0x0B => read_int_array(reader),
-            0x0C => read_long_array(reader),
-            data_type => Err(ErrorKind::InvalidTagType(data_type).into())
-        }?;
+        match stack.last_mut() {
+            Some(parent) => parent.push(name, tag),
+            None => return Ok(Some(tag)),
+        }
+        Ok(None)
+    }
+
+    /// Start reading a List or Compound, consuming the List header if present
+    fn open_frame(&mut self, tag_type: u8) -> Result<Frame> {
+        self.enter()?;
+        self.account(mem::size_of::<Tag>() as u64)?;
+        if tag_type == 0x0A {
+            return Ok(Frame::Compound { name: None, entries: Vec::new() });
+        }
 
-        container.push((tag_name, tag_value));
+        let element_type = self.inner.read_u8()?;
+        let length = self.inner.read_i32::<E>()?;
+        let length = self.account_length(length, 0)?;
+        Ok(Frame::List {
+            name: None,
+            element_type,
+            remaining: length,
+            elements: Vec::with_capacity(cmp::min(length, MAX_PREALLOCATION)),
+        })
     }
-    Ok(Tag::Compound(container))
+
+    /// Read the header of the next tag within `frame`, returning its type and
+    /// its name when inside a Compound, or `None` once the frame is complete
+    fn next_child(&mut self, frame: &mut Frame) -> Result<Option<(u8, Option<String>)>> {
+        match *frame {
+            Frame::Compound { ref mut entries, .. } => {
+                let tag_type = self.inner.read_u8()?;
+
+                if tag_type == 0x00 {
+                    entries.push((String::with_capacity(0), Tag::End));
+                    return Ok(None);
+                }
+
+                self.account(mem::size_of::<(String, Tag)>() as u64)?;
+                let tag_name = self.read_string_value()?;
+                Ok(Some((tag_type, Some(tag_name))))
+            },
+            Frame::List { ref mut remaining, element_type, .. } => {
+                if *remaining == 0 {
+                    return Ok(None);
+                }
+                *remaining -= 1;
+
+                // Like the vanilla server, a non-empty List of End tags is rejected as it
+                // would let a handful of bytes allocate an arbitrary number of elements
+                match element_type {
+                    0x01..=0x0C => Ok(Some((element_type, None))),
+                    data_type => Err(Error::UnexpectedElement { container: "List", element_type: data_type }),
+                }
+            },
+        }
+    }
+
+    fn read_byte_array_payload(&mut self) -> Result<Tag> {
+        let length = self.inner.read_i32::<E>()?;
+        let length = self.account_length(length, mem::size_of::<i8>())?;
+
+        let mut buf: Vec<i8> = Vec::with_capacity(cmp::min(length, MAX_PREALLOCATION));
+        for i in 0..length {
+            match self.inner.read_i8() {
+                Ok(val) => buf.push(val),
+                Err(..) => return Err(Error::TruncatedArray { expected: length, read: i }),
+            }
+        }
+        Ok(Tag::ByteArray(buf))
+    }
+
+    fn read_string_value(&mut self) -> Result<String> {
+        /*
+         * https://wiki.vg/NBT#Specification
+         *
+         * NBT strings are prefixed with *unsigned* 16-bit numbers
+         * despite the rest of the format using signed numbers
+         */
+        let length = self.inner.read_u16::<E>()? as usize;
+        self.account(length as u64)?;
+        let mut buf = Vec::with_capacity(length);
+        let read = (&mut self.inner).take(length as u64).read_to_end(&mut buf)?;
+        if read != length {
+            return Err(Error::TruncatedString { expected: length, read })
+        }
+        Ok(String::from_utf8(buf)?)
+    }
+
+    fn read_int_array_payload(&mut self) -> Result<Tag> {
+        let length = self.inner.read_i32::<E>()?;
+        let length = self.account_length(length, mem::size_of::<i32>())?;
+
+        let mut array_contents = Vec::with_capacity(cmp::min(length, MAX_PREALLOCATION));
+        for i in 0..length {
+            match self.inner.read_i32::<E>() {
+                Ok(value) => array_contents.push(value),
+                Err(..) => return Err(Error::TruncatedArray { expected: length, read: i }),
+            }
+        }
+        Ok(Tag::IntArray(array_contents))
+    }
+
+    fn read_long_array_payload(&mut self) -> Result<Tag> {
+        let length = self.inner.read_i32::<E>()?;
+        let length = self.account_length(length, mem::size_of::<i64>())?;
+
+        let mut array_contents = Vec::with_capacity(cmp::min(length, MAX_PREALLOCATION));
+        for i in 0..length {
+            match self.inner.read_i64::<E>() {
+                Ok(value) => array_contents.push(value),
+                Err(..) => return Err(Error::TruncatedArray { expected: length, read: i }),
+            }
+        }
+        Ok(Tag::LongArray(array_contents))
+    }
+}
+
+/// Keeps track of the number of bytes read from the wrapped reader
+struct Counter<R> {
+    inner: R,
+    offset: u64,
+}
+
+impl<R: Read> Read for Counter<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.offset += read as u64;
+        Ok(read)
+    }
+}
+
+/// The tag `NbtReader::read_nested` is currently reading
+struct Cursor {
+    /// Offset of the first byte of the tag, including the type and name in a Compound
+    start: u64,
+    /// Set once the header of a tag within the innermost frame has been read, holding
+    /// its name if the frame is a Compound
+    child: Option<Option<String>>,
+}
+
+/// Close every frame on the stack early, returning the outermost tag
+fn close_frames(mut stack: Vec<Frame>) -> Tag {
+    let mut tag = Tag::End;
+    while let Some(frame) = stack.pop() {
+        let (name, closed) = frame.close();
+        match stack.last_mut() {
+            Some(parent) => parent.push(name, closed),
+            None => tag = closed,
+        }
+    }
+    tag
+}
+
+/// Whether `mode` allows keeping the tree read up to the point `error` occurred.
+///
+/// Running out of input or data left over is recoverable in `ReadMode::Lenient`,
+/// corrupted tags only in `ReadMode::Recover`.
+fn is_recoverable(error: &Error, mode: ReadMode) -> bool {
+    let corrupted = match *error {
+        Error::Io(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => false,
+        Error::TruncatedString { .. } |
+        Error::TruncatedArray { .. } |
+        Error::LengthMismatch { .. } |
+        Error::TrailingData => false,
+        Error::InvalidTagType(..) |
+        Error::InvalidLength(..) |
+        Error::InvalidUtf8(..) |
+        Error::UnexpectedElement { .. } => true,
+        _ => return false,
+    };
+    match mode {
+        ReadMode::Strict => false,
+        ReadMode::Lenient => !corrupted,
+        ReadMode::Recover => true,
+    }
+}
+
+/// Iterator over consecutive root tags, created by `NbtReader::root_tags`
+pub struct RootTags<R, E = DesktopEndianness> {
+    reader: NbtReader<R, E>,
+    done: bool,
+}
+
+impl<R, E> RootTags<R, E> {
+    pub fn into_inner(self) -> NbtReader<R, E> {
+        self.reader
+    }
+}
+
+impl<R: Read, E: ByteOrder> Iterator for RootTags<R, E> {
+    type Item = Result<(String, Tag)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.reader.read_root_tag() {
+            Ok(Some(root)) => Some(Ok(root)),
+            Ok(None) => {
+                self.done = true;
+                None
+            },
+            Err(e) => {
+                // Reading cannot resume after an error, the position within the input is unknown
+                self.done = true;
+                Some(Err(e))
+            },
+        }
+    }
+}
+
+/// A List or Compound which is still being read by `NbtReader`
+enum Frame {
+    Compound {
+        name: Option<String>,
+        entries: Vec<(String, Tag)>,
+    },
+    List {
+        name: Option<String>,
+        element_type: u8,
+        remaining: usize,
+        elements: Vec<Tag>,
+    },
+}
+
+impl Frame {
+    fn name(&self) -> Option<&str> {
+        match *self {
+            Frame::Compound { ref name, .. } | Frame::List { ref name, .. } => name.as_ref().map(String::as_str),
+        }
+    }
+
+    fn set_name(&mut self, value: Option<String>) {
+        match *self {
+            Frame::Compound { ref mut name, .. } | Frame::List { ref mut name, .. } => *name = value,
+        }
+    }
+
+    /// Number of tags read into the frame so far
+    fn len(&self) -> usize {
+        match *self {
+            Frame::Compound { ref entries, .. } => entries.len(),
+            Frame::List { ref elements, .. } => elements.len(),
+        }
+    }
+
+    fn push(&mut self, name: Option<String>, tag: Tag) {
+        match *self {
+            Frame::Compound { ref mut entries, .. } => entries.push((name.unwrap_or_default(), tag)),
+            Frame::List { ref mut elements, .. } => elements.push(tag),
+        }
+    }
+
+    /// Finish the frame, returning the name it was read under and the completed tag
+    fn close(self) -> (Option<String>, Tag) {
+        match self {
+            Frame::Compound { name, entries } => (name, Tag::Compound(entries)),
+            Frame::List { name, elements, .. } => (name, Tag::List(elements)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// A single step in a `TagPath`
+pub enum PathSegment {
+    /// The name of a tag within a Compound
+    Name(String),
+    /// The position of a tag within a List
+    Index(usize),
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+/// Location of a tag relative to the tag reading started from, displayed
+/// like `Level.Sections[3].BlockStates`
+pub struct TagPath(pub Vec<PathSegment>);
+
+impl TagPath {
+    fn from_frames(stack: &[Frame], child: Option<Option<&str>>) -> Self {
+        let mut segments = Vec::with_capacity(stack.len());
+        for (parent, frame) in stack.iter().zip(stack.iter().skip(1)) {
+            segments.push(PathSegment::new(parent, frame.name()));
+        }
+        if let (Some(parent), Some(name)) = (stack.last(), child) {
+            segments.push(PathSegment::new(parent, name));
+        }
+        TagPath(segments)
+    }
+}
+
+impl PathSegment {
+    fn new(parent: &Frame, name: Option<&str>) -> Self {
+        match name {
+            Some(name) => PathSegment::Name(name.to_owned()),
+            None => PathSegment::Index(parent.len()),
+        }
+    }
+}
+
+impl fmt::Display for TagPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut empty = true;
+        for segment in &self.0 {
+            match *segment {
+                // Root tags are usually nameless, which would leave a leading separator
+                PathSegment::Name(ref name) if empty && name.is_empty() => continue,
+                PathSegment::Name(ref name) if empty => f.write_str(name)?,
+                PathSegment::Name(ref name) => write!(f, ".{}", name)?,
+                PathSegment::Index(index) => write!(f, "[{}]", index)?,
+            }
+            empty = false;
+        }
+        if empty {
+            f.write_str("<root>")?;
+        }
+        Ok(())
+    }
+}
+
+/// Iterate over root tags written back to back, see `NbtReader::root_tags`
+pub fn read_root_tags<R: Read>(reader: R) -> RootTags<R> {
+    NbtReader::new(reader).root_tags()
+}
+
+pub fn read_compound<R: Read>(reader: &mut R) -> Result<Tag> {
+    NbtReader::new(reader).read_compound()
 }
 
 #[inline]
 pub fn read_byte<R: Read>(reader: &mut R) -> Result<Tag> {
-    Ok(Tag::Byte(reader.read_i8()?))
+    NbtReader::new(reader).read_byte()
 }
 
 #[inline]
 pub fn read_short<R: Read>(reader: &mut R) -> Result<Tag> {
-    Ok(Tag::Short(reader.read_i16::<DesktopEndianness>()?))
+    NbtReader::new(reader).read_short()
 }
 
 #[inline]
 pub fn read_int<R: Read>(reader: &mut R) -> Result<Tag> {
-    Ok(Tag::Int(reader.read_i32::<DesktopEndianness>()?))
+    NbtReader::new(reader).read_int()
 }
 
 #[inline]
 pub fn read_long<R: Read>(reader: &mut R) -> Result<Tag> {
-    Ok(Tag::Long(reader.read_i64::<DesktopEndianness>()?))
+    NbtReader::new(reader).read_long()
 }
 
 #[inline]
 pub fn read_float<R: Read>(reader: &mut R) -> Result<Tag> {
-    Ok(Tag::Float(reader.read_f32::<DesktopEndianness>()?))
+    NbtReader::new(reader).read_float()
 }
 
 #[inline]
 pub fn read_double<R: Read>(reader: &mut R) -> Result<Tag> {
-    Ok(Tag::Double(reader.read_f64::<DesktopEndianness>()?))
+    NbtReader::new(reader).read_double()
 }
 
 pub fn read_byte_array<R: Read>(reader: &mut R) -> Result<Tag> {
-    let length = match read_int(reader)? {
-        Tag::Int(len) if len < 0 => Err(ErrorKind::InvalidHeaderLength(len)),
-        Tag::Int(len) => Ok(len),
-        _ => bail!("failed to read array length"),
-    }?;
-
-    let mut buf: Vec<i8> = Vec::with_capacity(length as usize);
-    for i in 0..length {
-        match read_byte(reader) {
-            Ok(Tag::Byte(val)) => {
-                buf.push(val);
-                Ok(())
-            },
-            Ok(tag) => Err(ErrorKind::UnexpectedElement(String::from("ByteArray"), tag.type_id())),
-            Err(..) if i < length => Err(ErrorKind::InvalidHeaderLength(i)),
-            Err(e) => Err(e.into()),
-        }?;
-    }
-    Ok(Tag::ByteArray(buf))
+    NbtReader::new(reader).read_byte_array()
 }
 
 pub fn read_string<R: Read>(reader: &mut R) -> Result<Tag> {
-    /*
-     * https://wiki.vg/NBT#Specification
-     *
-     * NBT strings are prefixed with *unsigned* 16-bit numbers
-     * despite the rest of the format using signed numbers
-     */
-    let length = reader.read_u16::<DesktopEndianness>()? as usize;
-    let mut buf = Vec::with_capacity(length);
-    let read = reader.take(length as u64).read_to_end(&mut buf)?;
-    if read != length {
-        bail!("Failed to read expected number of bytes")
-    }
-    let value = String::from_utf8(buf)?;
-    Ok(Tag::String(value))
+    NbtReader::new(reader).read_string()
 }
 
 pub fn read_list<R: Read>(reader: &mut R) -> Result<Tag> {
-    let mut tag_type: [u8; 1] = [0];
-    reader.read_exact(&mut tag_type)?;
-
-    if let Tag::Int(length) = read_int(reader)? {
-        let mut buf = Vec::with_capacity(length as usize);
-        for _ in 0..length {
-            buf.push(match tag_type[0] {
-                0x00 => Ok(Tag::End),
-                0x01 => read_byte(reader),
-                0x02 => read_short(reader),
-                0x03 => read_int(reader),
-                0x04 => read_long(reader),
-                0x05 => read_float(reader),
-                0x06 => read_double(reader),
-                0x07 => read_byte_array(reader),
-                0x08 => read_string(reader),
-                0x09 => read_list(reader),
-                0x0A => read_compound(reader),
-                0x0B => read_int_array(reader),
-                0x0C => read_long_array(reader),
-                data_type => Err(ErrorKind::UnexpectedElement(String::from("List"), data_type).into()),
-            }?);
-        }
-        return Ok(Tag::List(buf))
-    }
-    Err("Failed to read List Length header".into())
+    NbtReader::new(reader).read_list()
 }
 
 pub fn read_int_array<R: Read>(reader: &mut R) -> Result<Tag> {
-    if let Tag::Int(length) = read_int(reader)? {
-        let mut array_contents = Vec::with_capacity(length as usize);
-        for _ in 0..length {
-            match read_int(reader) {
-                Ok(Tag::Int(value)) => array_contents.push(value),
-                _ => bail!("Failed to read array contents")
-            }
-        }
-        return Ok(Tag::IntArray(array_contents))
-    }
-    bail!("Failed to read array length")
+    NbtReader::new(reader).read_int_array()
 }
 
 pub fn read_long_array<R: Read>(reader: &mut R) -> Result<Tag> {
-    if let Ok(Tag::Int(length)) = read_int(reader) {
-        let mut array_contents = Vec::with_capacity(length as usize);
-        for _ in 0..length {
-            match read_long(reader) {
-                Ok(Tag::Long(value)) => array_contents.push(value),
-                _ => bail!("Failed to read array contents")
-            }
-        }
-        return Ok(Tag::LongArray(array_contents))
-    }
-    bail!("Failed to read array length")
+    NbtReader::new(reader).read_long_array()
 }
 
 #[cfg(test)]
@@ -213,13 +963,15 @@ mod tests {
     use std::path::Path;
     use std::fs::File;
     use std::io::BufReader;
+    use std::io::Cursor;
+    use std::io::Read;
 
     #[test]
     fn test_reader() {
         let file = File::open(Path::new("./tests/hello_world.nbt"))
-            .chain_err(|| "Unable to open hello_world.nbt");
-        let mut reader = BufReader::new(file.unwrap());
-        match read_compound(&mut reader) {
+            .expect("Unable to open hello_world.nbt");
+        let mut reader = BufReader::new(file);
+        match read_file(&mut reader).map(|file| file.root) {
             Ok(Tag::Compound(mut tags)) => {
                 let (root_element, root_tag) = tags.pop()
                     .expect("Expected TAG_End");
@@ -251,9 +1003,222 @@ mod tests {
     fn test_bigtest() {
         // TODO: Improve test after improving fluency of codebase
         let file = File::open(Path::new("./tests/bigtest.nbt"))
-            .chain_err(|| "Unable to open bigtest.nbt");
-        let mut reader = BufReader::new(file.unwrap());
+            .expect("Unable to open bigtest.nbt");
+        let mut reader = BufReader::new(file);
         read_file(&mut reader)
             .expect("Failed to parse complex NBT structure");
     }
-}
\ No newline at end of file
+
+    fn assert_limit(result: Result<Tag>, expected: Limit) {
+        match result {
+            Err(e) => match *e.inner() {
+                Error::LimitExceeded { limit, .. } => assert_eq!(limit, expected),
+                _ => panic!("Expected {} to be exceeded, got {}", expected, e),
+            },
+            Ok(tag) => panic!("Expected {} to be exceeded, got Tag::{}", expected, tag.type_name()),
+        }
+    }
+
+    #[test]
+    fn test_length_limit() {
+        // An IntArray claiming i32::MAX elements without any actual contents
+        let data = [0x7f, 0xff, 0xff, 0xff];
+        let options = ReadOptions { max_length: 1024, ..ReadOptions::default() };
+        assert_limit(NbtReader::with_options(Cursor::new(&data[..]), options).read_int_array(), Limit::Length);
+
+        let options = ReadOptions::network();
+        assert_limit(NbtReader::with_options(Cursor::new(&data[..]), options).read_int_array(), Limit::Bytes);
+
+        assert!(read_int_array(&mut Cursor::new(&data[..])).is_err());
+    }
+
+    #[test]
+    fn test_depth_limit() {
+        // Lists of lists, nested far deeper than the default limit allows
+        let mut data = Vec::new();
+        for _ in 0..(DEFAULT_MAX_DEPTH + 1) {
+            data.extend_from_slice(&[0x09, 0x00, 0x00, 0x00, 0x01]);
+        }
+        data.extend_from_slice(&[0x00, 0x00, 0x00, 0x00, 0x00]);
+        assert_limit(read_list(&mut Cursor::new(&data)), Limit::Depth);
+    }
+
+    #[test]
+    fn test_error_position() {
+        // {"": {Level: {Sections: [{}, {BlockStates: <invalid tag type 0x0D>}]}}}
+        let data = [
+            0x0a, 0x00, 0x00,
+            0x0a, 0x00, 0x05, b'L', b'e', b'v', b'e', b'l',
+            0x09, 0x00, 0x08, b'S', b'e', b'c', b't', b'i', b'o', b'n', b's', 0x0a, 0x00, 0x00, 0x00, 0x02,
+            0x00,
+            0x0d, 0x00, 0x0b, b'B', b'l', b'o', b'c', b'k', b'S', b't', b'a', b't', b'e', b's',
+        ];
+
+        match read_compound(&mut Cursor::new(&data[..])) {
+            Err(Error::Position { offset, ref path, ref source }) => {
+                assert_eq!(offset, 28);
+                assert_eq!(path.to_string(), "Level.Sections[1].BlockStates");
+                match **source {
+                    Error::InvalidTagType(0x0D) => {},
+                    ref e => panic!("Expected an invalid tag type, got {}", e),
+                }
+            },
+            Err(e) => panic!("Expected the error to include its position, got {}", e),
+            Ok(..) => panic!("Expected an invalid tag type"),
+        }
+    }
+
+    #[test]
+    fn test_detect_format() {
+        let file = read_file(&mut BufReader::new(File::open("./tests/hello_world.nbt").unwrap()))
+            .expect("Failed to read uncompressed file");
+        assert_eq!(file.compression.to_str(), "None");
+
+        match read_file(&mut Cursor::new(Vec::new())) {
+            Err(Error::Io(ref e)) if e.kind() == io::ErrorKind::UnexpectedEof => {},
+            other => panic!("Expected an empty stream to be rejected, got {:?}", other),
+        }
+        match read_file(&mut Cursor::new(vec![0x42, 0x00, 0x00])) {
+            Err(Error::InvalidCompressionFormat(0x42)) => {},
+            other => panic!("Expected an unknown format to be rejected, got {:?}", other),
+        }
+        let bedrock = [0x0a, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x0a, 0x00, 0x00, 0x00];
+        let file = read_file(&mut Cursor::new(&bedrock[..])).expect("Failed to read Bedrock level.dat");
+        assert_eq!(file.storage_version, Some(10));
+
+        let truncated = [0x0a, 0x00, 0x00, 0x00, 0x06, 0x00, 0x00, 0x00, 0x0a, 0x00, 0x00, 0x00];
+        match read_file(&mut Cursor::new(&truncated[..])) {
+            Err(ref e) if matches!(*e.inner(), Error::LengthMismatch { expected: 6, read: 4 }) => {},
+            other => panic!("Expected the payload length to be checked, got {:?}", other),
+        }
+        let trailing = [0x0a, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x0a, 0x00, 0x00, 0x00, 0x00];
+        match read_file(&mut Cursor::new(&trailing[..])) {
+            Err(ref e) if matches!(*e.inner(), Error::TrailingData) => assert_eq!(e.offset(), Some(4)),
+            other => panic!("Expected data after the payload to be rejected, got {:?}", other),
+        }
+        let options = ReadOptions { mode: ReadMode::Lenient, ..ReadOptions::default() };
+        let file = read_file_with_options(&mut Cursor::new(&trailing[..]), options).unwrap();
+        assert_eq!(file.diagnostics.len(), 1);
+    }
+
+    #[test]
+    fn test_strict_and_lenient() {
+        // {"": {a: 1b, b: "xy"}}
+        let data = [
+            0x0a, 0x00, 0x00,
+            0x01, 0x00, 0x01, b'a', 0x01,
+            0x08, 0x00, 0x01, b'b', 0x00, 0x02, b'x', b'y',
+            0x00,
+        ];
+        let complete = read_file(&mut Cursor::new(&data[..])).unwrap();
+        assert!(complete.diagnostics.is_empty());
+
+        // Every way of cutting the file short is an error by default, but keeps the
+        // tags read so far when reading leniently
+        for length in 1..data.len() {
+            let mut reader = Cursor::new(&data[..length]);
+            assert!(read_file(&mut reader).is_err(), "Accepted {} of {} bytes", length, data.len());
+
+            let mut reader = Cursor::new(&data[..length]);
+            let file = read_file_with_options(&mut reader, ReadOptions::lenient()).unwrap();
+            assert_eq!(file.diagnostics.len(), 1);
+            assert!(file.diagnostics[0].offset <= length as u64);
+            let root = match file.root {
+                Tag::Compound(ref entries) => entries.first().map(|(_, root)| root),
+                ref tag => panic!("Expected Tag::Compound, got Tag::{}", tag.type_name()),
+            };
+            let entries = match root {
+                Some(Tag::Compound(entries)) => entries.len(),
+                _ => 0,
+            };
+            assert_eq!(entries, match length { 0..=7 => 0, 8..=15 => 1, _ => 2 });
+        }
+
+        let mut trailing = data.to_vec();
+        trailing.push(0x00);
+        match read_file(&mut Cursor::new(&trailing)) {
+            Err(ref e) if matches!(*e.inner(), Error::TrailingData) => assert_eq!(e.offset(), Some(17)),
+            other => panic!("Expected trailing data to be rejected, got {:?}", other),
+        }
+        let file = read_file_with_options(&mut Cursor::new(&trailing), ReadOptions::lenient()).unwrap();
+        assert_eq!(file.root, complete.root);
+        assert_eq!(file.diagnostics.len(), 1);
+
+        // Corrupted tags are never recovered from
+        let mut corrupted = data.to_vec();
+        corrupted[3] = 0x0d;
+        assert!(read_file_with_options(&mut Cursor::new(&corrupted), ReadOptions::lenient()).is_err());
+    }
+
+    #[test]
+    fn test_recover() {
+        // {"": {a: 1b, l: [{x: 1b}, {y: <invalid tag type 0x0D>, z: 2b}], s: "ok"}}
+        let data = [
+            0x0a, 0x00, 0x00,
+            0x01, 0x00, 0x01, b'a', 0x01,
+            0x09, 0x00, 0x01, b'l', 0x0a, 0x00, 0x00, 0x00, 0x02,
+            0x01, 0x00, 0x01, b'x', 0x01, 0x00,
+            0x0d, 0x00, 0x01, b'y', 0x00,
+            0x01, 0x00, 0x01, b'z', 0x02, 0x00,
+            0x08, 0x00, 0x01, b's', 0x00, 0x02, b'o', b'k',
+            0x00,
+        ];
+        assert!(read_file(&mut Cursor::new(&data[..])).is_err());
+        assert!(read_file_with_options(&mut Cursor::new(&data[..]), ReadOptions::lenient()).is_err());
+
+        let file = read_file_with_options(&mut Cursor::new(&data[..]), ReadOptions::recover()).unwrap();
+        assert_eq!(file.diagnostics.len(), 1);
+        assert_eq!(file.diagnostics[0].offset, 23);
+        assert_eq!(file.diagnostics[0].path.to_string(), "l[1].y");
+        match file.diagnostics[0].error {
+            Error::InvalidTagType(0x0D) => {},
+            ref e => panic!("Expected an invalid tag type, got {}", e),
+        }
+        let expected = Tag::Compound(vec![(String::new(), Tag::Compound(vec![
+            (String::from("a"), Tag::Byte(1)),
+            (String::from("l"), Tag::List(vec![
+                Tag::Compound(vec![(String::from("x"), Tag::Byte(1)), (String::new(), Tag::End)]),
+                Tag::Compound(Vec::new()),
+            ])),
+        ]))]);
+        assert_eq!(file.root, expected);
+
+        // Invalid UTF-8 and negative lengths are salvaged in the same way
+        let mut invalid_utf8 = data.to_vec();
+        invalid_utf8[40] = 0xff;
+        invalid_utf8[23] = 0x01;
+        let file = read_file_with_options(&mut Cursor::new(&invalid_utf8), ReadOptions::recover()).unwrap();
+        assert_eq!(file.diagnostics[0].path.to_string(), "s");
+        assert!(matches!(file.diagnostics[0].error, Error::InvalidUtf8(..)));
+
+        let mut negative = data.to_vec();
+        negative[13] = 0xff;
+        let file = read_file_with_options(&mut Cursor::new(&negative), ReadOptions::recover()).unwrap();
+        assert_eq!(file.diagnostics[0].path.to_string(), "l");
+        assert!(matches!(file.diagnostics[0].error, Error::InvalidLength(..)));
+    }
+
+    #[test]
+    fn test_root_tags() {
+        let mut data = Vec::new();
+        File::open("./tests/hello_world.nbt").unwrap().read_to_end(&mut data).unwrap();
+        let length = data.len();
+        data.extend_from_slice(&[0x03, 0x00, 0x01, b'x', 0x00, 0x00, 0x00, 0x2a]);
+        data.extend_from_within(..length);
+
+        let roots = read_root_tags(Cursor::new(&data)).collect::<Result<Vec<_>>>().unwrap();
+        assert_eq!(roots.len(), 3);
+        assert_eq!(roots[0].0, "hello world");
+        assert_eq!(roots[1], (String::from("x"), Tag::Int(42)));
+        assert_eq!(roots[0], roots[2]);
+
+        // Cutting the last root short must be reported rather than ending the iteration
+        for cut in 1..length {
+            let mut roots = read_root_tags(Cursor::new(&data[..data.len() - cut]));
+            assert!(roots.next().unwrap().is_ok());
+            assert!(roots.next().unwrap().is_ok());
+            assert!(roots.next().unwrap().is_err());
+            assert!(roots.next().is_none());
+        }
+    }
+}
@@ -0,0 +1,304 @@
+//! Region files of the Anvil format, the `.mca` files holding the chunks, entities and
+//! points of interest of a 32x32 area of chunks.
+//!
+//! A region starts with a header of two 4KiB tables, the first giving the offset and
+//! length in 4KiB sectors of every chunk and the second the time it was last saved.
+//! Each chunk is stored as its length, a byte identifying its compression and the
+//! compressed NBT data. Chunks too large for a region are stored in a `c.<x>.<z>.mcc`
+//! file next to it instead, which is only read when the folder of the region is known.
+use std::fs;
+use std::io;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+use byteorder::BigEndian;
+use byteorder::ByteOrder;
+use byteorder::ReadBytesExt;
+use byteorder::WriteBytesExt;
+use errors::*;
+
+use read::decompress;
+use read::NBTFile;
+use read::NbtReader;
+use tag::Compression;
+use tag::Tag;
+use write::write_file;
+
+pub const SECTOR_SIZE: usize = 4096;
+/// Chunks along each side of a region
+pub const REGION_CHUNKS: i32 = 32;
+
+const ENTRIES: usize = (REGION_CHUNKS * REGION_CHUNKS) as usize;
+const HEADER_SECTORS: u32 = 2;
+/// Set on the compression byte of chunks too large for a region, which are instead
+/// stored in a `c.<x>.<z>.mcc` file next to it
+const EXTERNAL: u8 = 0x80;
+/// Length and compression byte preceding the data of a chunk
+const CHUNK_HEADER_LENGTH: usize = 5;
+
+/// The byte identifying a compression format within a region file
+fn compression_id(compression: &Compression) -> Result<u8> {
+    match *compression {
+        Compression::Gzip => Ok(1),
+        Compression::Zlib => Ok(2),
+        Compression::None => Ok(3),
+        Compression::Lz4 => Ok(4),
+        Compression::Lz4Frame => Err(Error::UnsupportedFormat("LZ4 frames within a region file")),
+    }
+}
+
+fn compression_from_id(id: u8) -> Result<Compression> {
+    match id {
+        1 => Ok(Compression::Gzip),
+        2 => Ok(Compression::Zlib),
+        3 => Ok(Compression::None),
+        4 => Ok(Compression::Lz4),
+        _ => Err(Error::InvalidCompressionFormat(id)),
+    }
+}
+
+/// Position of a chunk within the header, chunk coordinates are taken modulo 32 so
+/// both world and local coordinates can be used
+fn entry(x: i32, z: i32) -> usize {
+    ((x & (REGION_CHUNKS - 1)) + (z & (REGION_CHUNKS - 1)) * REGION_CHUNKS) as usize
+}
+
+/// A region file which is read from and written to in place
+pub struct RegionFile<S> {
+    inner: S,
+    /// Offset of every chunk in sectors shifted left by 8, or'ed with its length in sectors
+    locations: Vec<u32>,
+    /// Seconds since the UNIX epoch every chunk was last saved at
+    timestamps: Vec<u32>,
+    /// Folder and region coordinates of the region, to find external chunks
+    external: Option<(PathBuf, i32, i32)>,
+}
+
+impl<S: Read + Seek> RegionFile<S> {
+    /// Read the header of a region, an empty file is treated as a region without chunks
+    pub fn open(mut inner: S) -> Result<Self> {
+        let mut header = vec![0u8; HEADER_SECTORS as usize * SECTOR_SIZE];
+        inner.seek(SeekFrom::Start(0))?;
+        let mut read = 0;
+        while read < header.len() {
+            match inner.read(&mut header[read..]) {
+                Ok(0) => break,
+                Ok(length) => read += length,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {},
+                Err(e) => return Err(e.into()),
+            }
+        }
+        if read != 0 && read != header.len() {
+            return Err(Error::LengthMismatch { expected: header.len() as u64, read: read as u64 });
+        }
+
+        let mut locations = vec![0; ENTRIES];
+        let mut timestamps = vec![0; ENTRIES];
+        BigEndian::read_u32_into(&header[..SECTOR_SIZE], &mut locations);
+        BigEndian::read_u32_into(&header[SECTOR_SIZE..], &mut timestamps);
+        Ok(RegionFile { inner, locations, timestamps, external: None })
+    }
+
+    /// Read chunks stored in external `.mcc` files from `folder`, the folder holding
+    /// the region at region coordinates `x` and `z`
+    pub fn with_external_folder<P: Into<PathBuf>>(mut self, folder: P, x: i32, z: i32) -> Self {
+        self.external = Some((folder.into(), x, z));
+        self
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    pub fn contains(&self, x: i32, z: i32) -> bool {
+        self.locations[entry(x, z)] != 0
+    }
+
+    /// Seconds since the UNIX epoch the chunk was last saved at, 0 for missing chunks
+    pub fn timestamp(&self, x: i32, z: i32) -> u32 {
+        self.timestamps[entry(x, z)]
+    }
+
+    /// Local coordinates of every chunk held by the region
+    pub fn chunks<'a>(&'a self) -> impl Iterator<Item = (i32, i32)> + 'a {
+        (0..ENTRIES)
+            .filter(move |&index| self.locations[index] != 0)
+            .map(|index| (index as i32 % REGION_CHUNKS, index as i32 / REGION_CHUNKS))
+    }
+
+    /// Read the compressed data of a chunk and its compression format
+    fn read_payload(&mut self, x: i32, z: i32) -> Result<Option<(Compression, Vec<u8>)>> {
+        let location = self.locations[entry(x, z)];
+        if location == 0 {
+            return Ok(None);
+        }
+        let (offset, sectors) = (u64::from(location >> 8), (location & 0xff) as usize);
+        self.inner.seek(SeekFrom::Start(offset * SECTOR_SIZE as u64))?;
+
+        let length = self.inner.read_u32::<BigEndian>()? as usize;
+        if length == 0 || length + 4 > sectors * SECTOR_SIZE {
+            return Err(Error::LengthMismatch { expected: (sectors * SECTOR_SIZE) as u64, read: length as u64 + 4 });
+        }
+        let id = self.inner.read_u8()?;
+        let compression = compression_from_id(id & !EXTERNAL)?;
+        if id & EXTERNAL != 0 {
+            let (ref folder, region_x, region_z) = *self.external.as_ref()
+                .ok_or(Error::UnsupportedFormat("chunk stored in an external .mcc file"))?;
+            let x = region_x * REGION_CHUNKS + (x & (REGION_CHUNKS - 1));
+            let z = region_z * REGION_CHUNKS + (z & (REGION_CHUNKS - 1));
+            return Ok(Some((compression, fs::read(folder.join(format!("c.{}.{}.mcc", x, z)))?)));
+        }
+        let mut payload = vec![0; length - 1];
+        self.inner.read_exact(&mut payload)?;
+        Ok(Some((compression, payload)))
+    }
+
+    /// Read the root Compound of a chunk, if the region holds it
+    pub fn read_chunk(&mut self, x: i32, z: i32) -> Result<Option<Tag>> {
+        let (compression, payload) = match self.read_payload(x, z)? {
+            Some(payload) => payload,
+            None => return Ok(None),
+        };
+        let mut reader = NbtReader::new(decompress(io::Cursor::new(payload), &compression));
+        match reader.read_file_root()? {
+            Tag::Compound(mut entries) if !entries.is_empty() => Ok(Some(entries.swap_remove(0).1)),
+            _ => Err(Error::MissingTag(String::from("chunk"))),
+        }
+    }
+}
+
+impl<S: Read + Write + Seek> RegionFile<S> {
+    /// Write the header of a region without chunks
+    pub fn create(mut inner: S) -> Result<Self> {
+        inner.seek(SeekFrom::Start(0))?;
+        inner.write_all(&[0; HEADER_SECTORS as usize * SECTOR_SIZE])?;
+        Ok(RegionFile { inner, locations: vec![0; ENTRIES], timestamps: vec![0; ENTRIES], external: None })
+    }
+
+    fn write_header_entry(&mut self, index: usize) -> Result<()> {
+        self.inner.seek(SeekFrom::Start(index as u64 * 4))?;
+        self.inner.write_u32::<BigEndian>(self.locations[index])?;
+        self.inner.seek(SeekFrom::Start((SECTOR_SIZE + index * 4) as u64))?;
+        self.inner.write_u32::<BigEndian>(self.timestamps[index])?;
+        Ok(())
+    }
+
+    /// First run of `sectors` free sectors, reusing the sectors of the chunk at `index`
+    fn allocate(&self, index: usize, sectors: u32) -> u32 {
+        let mut used: Vec<(u32, u32)> = self.locations.iter().enumerate()
+            .filter(|&(other, &location)| other != index && location != 0)
+            .map(|(_, &location)| (location >> 8, (location >> 8) + (location & 0xff)))
+            .collect();
+        used.sort_unstable();
+
+        let mut start = HEADER_SECTORS;
+        for (used_start, used_end) in used {
+            if used_start >= start + sectors {
+                break;
+            }
+            start = start.max(used_end);
+        }
+        start
+    }
+
+    /// Compress and write a chunk, replacing any chunk already stored at its position
+    pub fn write_chunk(&mut self, x: i32, z: i32, chunk: &Tag, compression: Compression) -> Result<()> {
+        let id = compression_id(&compression)?;
+        let mut data = vec![0; CHUNK_HEADER_LENGTH];
+        write_file(&mut data, &NBTFile::with_root(compression, chunk.clone()))?;
+        let length = data.len() - 4;
+        BigEndian::write_u32(&mut data[..4], length as u32);
+        data[4] = id;
+
+        let sectors = data.len().div_ceil(SECTOR_SIZE);
+        if sectors > 0xff {
+            return Err(Error::UnsupportedFormat("chunk too large for a region file"));
+        }
+        data.resize(sectors * SECTOR_SIZE, 0);
+
+        let index = entry(x, z);
+        let offset = self.allocate(index, sectors as u32);
+        self.inner.seek(SeekFrom::Start(u64::from(offset) * SECTOR_SIZE as u64))?;
+        self.inner.write_all(&data)?;
+
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs());
+        self.locations[index] = offset << 8 | sectors as u32;
+        self.timestamps[index] = now as u32;
+        self.write_header_entry(index)
+    }
+
+    /// Remove a chunk from the header, its sectors are reused by later writes
+    pub fn remove_chunk(&mut self, x: i32, z: i32) -> Result<()> {
+        let index = entry(x, z);
+        self.locations[index] = 0;
+        self.timestamps[index] = 0;
+        self.write_header_entry(index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_region() {
+        let mut region = RegionFile::create(Cursor::new(Vec::new())).unwrap();
+        let small = Tag::compound(vec![("xPos", Tag::Int(-1)), ("zPos", Tag::Int(2))]);
+        // Random longs do not compress, so this chunk takes up three sectors
+        let noise: Vec<i64> = (0..1500i64).map(|value| value.wrapping_mul(0x5851_f42d_4c95_7f2d)).collect();
+        let large = Tag::compound(vec![("data", Tag::LongArray(noise))]);
+
+        region.write_chunk(-1, 2, &small, Compression::Zlib).unwrap();
+        region.write_chunk(3, 0, &large, Compression::Gzip).unwrap();
+        region.write_chunk(4, 0, &small, Compression::None).unwrap();
+        assert!(region.contains(31, 2));
+        assert!(region.timestamp(31, 2) > 0);
+        assert_eq!(region.chunks().collect::<Vec<_>>(), vec![(3, 0), (4, 0), (31, 2)]);
+
+        // Growing the first chunk moves it behind the others
+        region.write_chunk(31, 2, &large, Compression::Lz4).unwrap();
+        assert_eq!(region.locations[entry(31, 2)] >> 8, 7);
+        region.remove_chunk(4, 0).unwrap();
+        region.write_chunk(5, 0, &small, Compression::Zlib).unwrap();
+        assert_eq!(region.locations[entry(5, 0)] >> 8, 2);
+
+        let mut reread = RegionFile::open(region.into_inner()).unwrap();
+        assert_eq!(reread.read_chunk(31, 2).unwrap(), Some(large.clone()));
+        assert_eq!(reread.read_chunk(3, 0).unwrap(), Some(large));
+        assert_eq!(reread.read_chunk(5, 0).unwrap(), Some(small));
+        assert_eq!(reread.read_chunk(4, 0).unwrap(), None);
+        assert!(RegionFile::open(Cursor::new(Vec::new())).unwrap().chunks().next().is_none());
+        assert!(RegionFile::open(Cursor::new(vec![0; 100])).is_err());
+    }
+
+    #[test]
+    fn test_external_chunk() {
+        let folder = env::temp_dir().join(format!("nbt-region-{}", std::process::id()));
+        fs::create_dir_all(&folder).unwrap();
+        let chunk = Tag::compound(vec![("xPos", Tag::Int(-1)), ("zPos", Tag::Int(2))]);
+        let mut data = Vec::new();
+        write_file(&mut data, &NBTFile::with_root(Compression::Zlib, chunk.clone())).unwrap();
+        fs::write(folder.join("c.-1.2.mcc"), data).unwrap();
+
+        // The region only holds the length and compression byte of the chunk
+        let mut region = RegionFile::create(Cursor::new(Vec::new())).unwrap();
+        region.write_chunk(-1, 2, &chunk, Compression::Zlib).unwrap();
+        let offset = (region.locations[entry(-1, 2)] >> 8) as usize * SECTOR_SIZE;
+        let mut bytes = region.into_inner().into_inner();
+        bytes[offset..offset + CHUNK_HEADER_LENGTH].copy_from_slice(&[0, 0, 0, 1, EXTERNAL | 2]);
+
+        let mut region = RegionFile::open(Cursor::new(bytes)).unwrap();
+        assert!(region.read_chunk(31, 2).is_err());
+        let mut region = region.with_external_folder(&folder, -1, 0);
+        assert_eq!(region.read_chunk(31, 2).unwrap(), Some(chunk.clone()));
+        assert_eq!(region.read_chunk(-1, 2).unwrap(), Some(chunk));
+
+        fs::remove_dir_all(&folder).unwrap();
+    }
+}
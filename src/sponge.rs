@@ -0,0 +1,363 @@
+//! Sponge schematics, the `.schem` files used by WorldEdit, in versions 2 and 3.
+//!
+//! Blocks are stored as indices into a palette of block state strings, encoded as
+//! VarInts in a ByteArray and ordered by x, then z, then y. Version 3 moves the blocks
+//! into a `Blocks` Compound and the extra data of block entities into `Data`.
+use std::convert::TryFrom;
+use std::io::BufRead;
+use std::io::Write;
+use errors::*;
+
+use block::BlockState;
+use fields;
+use read::read_file;
+use read::NBTFile;
+use tag::Compression;
+use tag::Tag;
+use write::write_file;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Schematic {
+    /// Either 2 or 3, which decides the layout used when writing
+    pub version: i32,
+    pub data_version: i32,
+    pub width: u16,
+    pub height: u16,
+    pub length: u16,
+    /// Offset of the schematic relative to the position it was copied from
+    pub offset: [i32; 3],
+    pub metadata: Option<Tag>,
+    pub palette: Vec<BlockState>,
+    /// Palette index of every block, see `Schematic::index`
+    pub blocks: Vec<usize>,
+    pub block_entities: Vec<SchematicEntity<[i32; 3]>>,
+    pub entities: Vec<SchematicEntity<[f64; 3]>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// A block entity or entity, positioned relative to the schematic
+pub struct SchematicEntity<P> {
+    pub pos: P,
+    pub id: String,
+    /// The remaining data of the (block) entity, as a Compound
+    pub data: Tag,
+}
+
+impl Schematic {
+    /// Create a version 3 schematic filled with air
+    pub fn new(width: u16, height: u16, length: u16, data_version: i32) -> Self {
+        Schematic {
+            version: 3,
+            data_version,
+            width,
+            height,
+            length,
+            offset: [0; 3],
+            metadata: None,
+            palette: vec![BlockState::air()],
+            blocks: vec![0; width as usize * height as usize * length as usize],
+            block_entities: Vec::new(),
+            entities: Vec::new(),
+        }
+    }
+
+    /// Read a schematic file, the compression is detected automatically
+    pub fn read<R: BufRead>(reader: &mut R) -> Result<Self> {
+        let file = read_file(reader)?;
+        Schematic::from_tag(file.root_tag().ok_or_else(|| Error::MissingTag(String::from("Schematic")))?)
+    }
+
+    /// Write a gzip compressed schematic file, as WorldEdit does
+    pub fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+        let root = match self.version {
+            2 => ("Schematic", self.to_tag()),
+            _ => ("", Tag::compound(vec![("Schematic", self.to_tag())])),
+        };
+        let root = Tag::Compound(vec![(String::from(root.0), root.1)]);
+        write_file(writer, &NBTFile::new(Compression::Gzip, root))
+    }
+
+    /// Read the root tag of a schematic, in version 3 this wraps the `Schematic` Compound
+    pub fn from_tag(tag: &Tag) -> Result<Self> {
+        let tag = match tag.get("Schematic") {
+            Some(schematic) => schematic,
+            None => tag,
+        };
+        let version = fields::int(tag, "Version")?;
+        let (blocks, entity_data): (&Tag, fn(&Tag) -> Result<Tag>) = match version {
+            2 => (tag, |entity| Ok(without(entity, &["Pos", "Id"]))),
+            3 => (fields::field(tag, "Blocks")?, |entity| Ok(match entity.get("Data") {
+                Some(data) => data.clone(),
+                None => Tag::compound(Vec::<(String, Tag)>::new()),
+            })),
+            _ => return Err(fields::invalid("Version", "version 2 or 3")),
+        };
+
+        let (width, height, length) = (dimension(tag, "Width")?, dimension(tag, "Height")?, dimension(tag, "Length")?);
+
+        let palette = fields::field(blocks, "Palette")?.entries().ok_or_else(|| fields::invalid("Palette", "a Compound"))?;
+        let mut states = Vec::new();
+        for (state, index) in palette {
+            let index = index.as_i32().filter(|&index| index >= 0).ok_or_else(|| fields::invalid(state, "a palette index"))?;
+            states.push((index as usize, state.parse::<BlockState>()?));
+        }
+        states.sort_by_key(|&(index, _)| index);
+        if states.iter().enumerate().any(|(expected, &(index, _))| index != expected) {
+            return Err(fields::invalid("Palette", "consecutive indices"));
+        }
+        let palette: Vec<BlockState> = states.into_iter().map(|(_, state)| state).collect();
+
+        // The dimensions are only trusted once they match the decoded blocks
+        let data = fields::byte_array(blocks, if version == 2 { "BlockData" } else { "Data" })?;
+        let block_data = decode_varints(data)?;
+        let volume = (width as usize).checked_mul(height as usize).and_then(|area| area.checked_mul(length as usize));
+        if volume != Some(block_data.len()) {
+            return Err(fields::invalid("BlockData", "one entry for every block"));
+        }
+        if block_data.iter().any(|&index| index >= palette.len()) {
+            return Err(fields::invalid("BlockData", "indices into the palette"));
+        }
+
+        let mut schematic = Schematic {
+            version,
+            data_version: fields::int(tag, "DataVersion")?,
+            width,
+            height,
+            length,
+            offset: fields::optional(tag, "Offset", fields::int_vec3)?.unwrap_or([0; 3]),
+            metadata: tag.get("Metadata").cloned(),
+            palette,
+            blocks: block_data,
+            block_entities: Vec::new(),
+            entities: Vec::new(),
+        };
+
+        if blocks.get("BlockEntities").is_some() {
+            for entity in fields::list(blocks, "BlockEntities")? {
+                schematic.block_entities.push(SchematicEntity {
+                    pos: fields::int_vec3(entity, "Pos")?,
+                    id: fields::string(entity, "Id")?.to_owned(),
+                    data: entity_data(entity)?,
+                });
+            }
+        }
+        if tag.get("Entities").is_some() {
+            for entity in fields::list(tag, "Entities")? {
+                schematic.entities.push(SchematicEntity {
+                    pos: fields::double_vec3(entity, "Pos")?,
+                    id: fields::string(entity, "Id")?.to_owned(),
+                    data: entity_data(entity)?,
+                });
+            }
+        }
+        Ok(schematic)
+    }
+
+    /// The `Schematic` Compound, in version 3 this must be wrapped in the root tag
+    pub fn to_tag(&self) -> Tag {
+        let mut tag = Tag::compound(vec![
+            ("Version", Tag::Int(self.version)),
+            ("DataVersion", Tag::Int(self.data_version)),
+            ("Width", Tag::Short(self.width as i16)),
+            ("Height", Tag::Short(self.height as i16)),
+            ("Length", Tag::Short(self.length as i16)),
+            ("Offset", Tag::IntArray(self.offset.to_vec())),
+        ]);
+        if let Some(ref metadata) = self.metadata {
+            tag.insert("Metadata", metadata.clone());
+        }
+
+        let palette = Tag::compound(self.palette.iter().enumerate()
+            .map(|(index, state)| (state.to_string(), Tag::Int(index as i32))));
+        let data = Tag::ByteArray(encode_varints(&self.blocks));
+        let entity = |pos: Tag, entity_id: &str, data: &Tag| {
+            let mut tag = if self.version == 2 {
+                data.clone()
+            } else {
+                Tag::compound(vec![("Data", data.clone())])
+            };
+            tag.insert("Pos", pos);
+            tag.insert("Id", Tag::from(entity_id));
+            tag
+        };
+        let block_entities = Tag::List(self.block_entities.iter()
+            .map(|block| entity(Tag::IntArray(block.pos.to_vec()), &block.id, &block.data))
+            .collect());
+        let entities = Tag::List(self.entities.iter()
+            .map(|entity_data| entity(fields::double_list(entity_data.pos), &entity_data.id, &entity_data.data))
+            .collect());
+
+        if self.version == 2 {
+            tag.insert("PaletteMax", Tag::Int(self.palette.len() as i32));
+            tag.insert("Palette", palette);
+            tag.insert("BlockData", data);
+            tag.insert("BlockEntities", block_entities);
+        } else {
+            tag.insert("Blocks", Tag::compound(vec![
+                ("Palette", palette),
+                ("Data", data),
+                ("BlockEntities", block_entities),
+            ]));
+        }
+        tag.insert("Entities", entities);
+        tag
+    }
+
+    pub fn volume(&self) -> usize {
+        self.width as usize * self.height as usize * self.length as usize
+    }
+
+    /// Index of a position within `blocks`, if it is inside of the schematic
+    pub fn index(&self, x: u16, y: u16, z: u16) -> Option<usize> {
+        if x >= self.width || y >= self.height || z >= self.length {
+            return None;
+        }
+        let (width, length) = (self.width as usize, self.length as usize);
+        Some(x as usize + z as usize * width + y as usize * width * length)
+    }
+
+    pub fn block(&self, x: u16, y: u16, z: u16) -> Option<&BlockState> {
+        self.index(x, y, z).map(|index| &self.palette[self.blocks[index]])
+    }
+
+    /// Set the block at a position, adding its state to the palette when needed
+    ///
+    /// # Panics
+    ///
+    /// Panics if the position is outside of the schematic
+    pub fn set_block(&mut self, x: u16, y: u16, z: u16, state: BlockState) {
+        let index = self.index(x, y, z).expect("position outside of the schematic");
+        self.blocks[index] = match self.palette.iter().position(|existing| *existing == state) {
+            Some(palette_index) => palette_index,
+            None => {
+                self.palette.push(state);
+                self.palette.len() - 1
+            },
+        };
+    }
+}
+
+/// A dimension, stored as a Short holding an unsigned value
+fn dimension(tag: &Tag, name: &str) -> Result<u16> {
+    match *fields::field(tag, name)? {
+        Tag::Short(value) => Ok(value as u16),
+        ref value => value.as_i32()
+            .and_then(|value| u16::try_from(value).ok())
+            .ok_or_else(|| fields::invalid(name, "an unsigned Short")),
+    }
+}
+
+/// A copy of a Compound without the given entries
+fn without(tag: &Tag, names: &[&str]) -> Tag {
+    let mut tag = tag.clone();
+    for name in names {
+        tag.remove(name);
+    }
+    tag
+}
+
+/// Decode unsigned LEB128 VarInts, as used for block data
+pub fn decode_varints(data: &[i8]) -> Result<Vec<usize>> {
+    let mut values = Vec::with_capacity(data.len());
+    let mut value: u32 = 0;
+    let mut shift = 0;
+    for &byte in data {
+        let byte = byte as u8;
+        if shift >= 32 || (shift == 28 && byte & 0x70 != 0) {
+            return Err(fields::invalid("BlockData", "VarInts of at most 32 bits"));
+        }
+        value |= u32::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            values.push(value as usize);
+            value = 0;
+            shift = 0;
+        } else {
+            shift += 7;
+        }
+    }
+    if shift != 0 {
+        return Err(fields::invalid("BlockData", "complete VarInts"));
+    }
+    Ok(values)
+}
+
+/// Encode values as unsigned LEB128 VarInts, as used for block data
+pub fn encode_varints(values: &[usize]) -> Vec<i8> {
+    let mut data = Vec::with_capacity(values.len());
+    for &value in values {
+        let mut value = value as u32;
+        while value >= 0x80 {
+            data.push((value as u8 | 0x80) as i8);
+            value >>= 7;
+        }
+        data.push(value as i8);
+    }
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_varints() {
+        let values = vec![0, 1, 127, 128, 300, 16384, u32::MAX as usize];
+        let encoded = encode_varints(&values);
+        assert_eq!(&encoded[..6], &[0, 1, 127, -128, 1, -84]);
+        assert_eq!(decode_varints(&encoded).unwrap(), values);
+        assert!(decode_varints(&[-128]).is_err());
+        assert!(decode_varints(&[-1, -1, -1, -1, -1, 1]).is_err());
+    }
+
+    #[test]
+    fn test_schematic() {
+        for &version in &[2, 3] {
+            let mut schematic = Schematic::new(3, 2, 4, 3465);
+            schematic.version = version;
+            schematic.offset = [-1, 0, 5];
+            let sign = BlockState::new("minecraft:oak_sign").with_property("rotation", "4");
+            schematic.set_block(2, 1, 3, sign.clone());
+            schematic.set_block(0, 0, 0, BlockState::new("minecraft:stone"));
+            schematic.block_entities.push(SchematicEntity {
+                pos: [2, 1, 3],
+                id: String::from("minecraft:sign"),
+                data: Tag::compound(vec![("is_waxed", Tag::Byte(1))]),
+            });
+            schematic.entities.push(SchematicEntity {
+                pos: [1.5, 1.0, 0.5],
+                id: String::from("minecraft:armor_stand"),
+                data: Tag::compound(vec![("Invisible", Tag::Byte(1))]),
+            });
+
+            let mut written = Vec::new();
+            schematic.write(&mut written).unwrap();
+            let reread = Schematic::read(&mut Cursor::new(written)).unwrap();
+            assert_eq!(reread, schematic);
+            assert_eq!(reread.block(2, 1, 3), Some(&sign));
+            assert_eq!(reread.blocks[reread.index(2, 1, 3).unwrap()], 1);
+            assert_eq!(reread.block(3, 0, 0), None);
+        }
+    }
+
+    #[test]
+    fn test_hostile_dimensions() {
+        // The dimensions must not be used before they are checked against the block data
+        let mut tag = Schematic::new(1, 1, 1, 3465).to_tag();
+        for &name in &["Width", "Height", "Length"] {
+            tag.insert(name, Tag::Short(-1));
+        }
+        assert!(Schematic::from_tag(&tag).is_err());
+
+        let mut tag = Schematic::new(1, 1, 1, 3465).to_tag();
+        tag.insert("Width", Tag::Int(1));
+        assert!(Schematic::from_tag(&tag).is_ok());
+        for &width in &[-1, 65537] {
+            tag.insert("Width", Tag::Int(width));
+            match Schematic::from_tag(&tag) {
+                Err(Error::InvalidTag { ref name, .. }) => assert_eq!(name, "Width"),
+                other => panic!("unexpected {:?}", other),
+            }
+        }
+    }
+}
@@ -0,0 +1,256 @@
+//! Structure files as saved by structure blocks and found in
+//! `generated/<namespace>/structures`, which are gzip compressed
+use std::io::BufRead;
+use std::io::Write;
+use errors::*;
+
+use block::BlockState;
+use fields;
+use read::read_file;
+use read::NBTFile;
+use tag::Compression;
+use tag::Tag;
+use write::write_file;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Structure {
+    pub data_version: Option<i32>,
+    pub size: [i32; 3],
+    /// Block states referenced by `StructureBlock::state`. Most structures have a
+    /// single palette, those with several pick one at random when placed. All palettes
+    /// have the same length. There is always at least one when read, and an empty
+    /// palette is written when there are none.
+    pub palettes: Vec<Vec<BlockState>>,
+    pub blocks: Vec<StructureBlock>,
+    pub entities: Vec<StructureEntity>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StructureBlock {
+    /// Position relative to the origin of the structure
+    pub pos: [i32; 3],
+    /// Index into the palette
+    pub state: usize,
+    /// Block entity data, without its position
+    pub nbt: Option<Tag>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StructureEntity {
+    /// Exact position relative to the origin of the structure
+    pub pos: [f64; 3],
+    /// Position of the block the entity is in
+    pub block_pos: [i32; 3],
+    /// Entity data, without its position
+    pub nbt: Tag,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// A block of a structure with its state looked up in a palette
+pub struct ResolvedBlock<'a> {
+    pub pos: [i32; 3],
+    pub state: &'a BlockState,
+    pub nbt: Option<&'a Tag>,
+}
+
+impl Structure {
+    pub fn new(size: [i32; 3]) -> Self {
+        Structure {
+            data_version: None,
+            size,
+            palettes: vec![Vec::new()],
+            blocks: Vec::new(),
+            entities: Vec::new(),
+        }
+    }
+
+    /// Read a structure file, the compression is detected automatically
+    pub fn read<R: BufRead>(reader: &mut R) -> Result<Self> {
+        let file = read_file(reader)?;
+        Structure::from_tag(file.root_tag().ok_or_else(|| Error::MissingTag(String::from("root")))?)
+    }
+
+    /// Write a gzip compressed structure file, as Minecraft does
+    pub fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+        write_file(writer, &NBTFile::with_root(Compression::Gzip, self.to_tag()))
+    }
+
+    pub fn from_tag(tag: &Tag) -> Result<Self> {
+        let palettes = match (tag.get("palette"), tag.get("palettes")) {
+            (Some(..), _) => vec![palette(fields::list(tag, "palette")?)?],
+            (None, Some(..)) => {
+                let mut palettes = Vec::new();
+                for states in fields::list(tag, "palettes")? {
+                    palettes.push(palette(states.as_list().ok_or_else(|| fields::invalid("palettes", "Lists"))?)?);
+                }
+                palettes
+            },
+            (None, None) => return Err(Error::MissingTag(String::from("palette"))),
+        };
+        let states = match palettes.first() {
+            Some(first) if palettes.iter().all(|palette| palette.len() == first.len()) => first.len(),
+            Some(..) => return Err(fields::invalid("palettes", "palettes of the same length")),
+            None => return Err(fields::invalid("palettes", "at least one palette")),
+        };
+
+        let mut blocks = Vec::new();
+        for block in fields::list(tag, "blocks")? {
+            let state = fields::int(block, "state")?;
+            if state < 0 || state as usize >= states {
+                return Err(fields::invalid("state", "an index into the palette"));
+            }
+            blocks.push(StructureBlock {
+                pos: fields::int_vec3(block, "pos")?,
+                state: state as usize,
+                nbt: block.get("nbt").cloned(),
+            });
+        }
+
+        let mut entities = Vec::new();
+        if tag.get("entities").is_some() {
+            for entity in fields::list(tag, "entities")? {
+                entities.push(StructureEntity {
+                    pos: fields::double_vec3(entity, "pos")?,
+                    block_pos: fields::int_vec3(entity, "blockPos")?,
+                    nbt: fields::field(entity, "nbt")?.clone(),
+                });
+            }
+        }
+
+        Ok(Structure {
+            data_version: fields::optional(tag, "DataVersion", fields::int)?,
+            size: fields::int_vec3(tag, "size")?,
+            palettes,
+            blocks,
+            entities,
+        })
+    }
+
+    pub fn to_tag(&self) -> Tag {
+        let states = |palette: &Vec<BlockState>| Tag::List(palette.iter().map(BlockState::to_tag).collect());
+        let mut tag = Tag::compound(vec![("size", fields::int_list(self.size))]);
+        match self.palettes.len() {
+            0 => tag.insert("palette", Tag::List(Vec::new())),
+            1 => tag.insert("palette", states(&self.palettes[0])),
+            _ => tag.insert("palettes", Tag::List(self.palettes.iter().map(states).collect())),
+        };
+
+        tag.insert("blocks", Tag::List(self.blocks.iter().map(|block| {
+            let mut entry = Tag::compound(vec![
+                ("pos", fields::int_list(block.pos)),
+                ("state", Tag::Int(block.state as i32)),
+            ]);
+            if let Some(ref nbt) = block.nbt {
+                entry.insert("nbt", nbt.clone());
+            }
+            entry
+        }).collect()));
+        tag.insert("entities", Tag::List(self.entities.iter().map(|entity| Tag::compound(vec![
+            ("pos", fields::double_list(entity.pos)),
+            ("blockPos", fields::int_list(entity.block_pos)),
+            ("nbt", entity.nbt.clone()),
+        ])).collect()));
+
+        if let Some(data_version) = self.data_version {
+            tag.insert("DataVersion", Tag::Int(data_version));
+        }
+        tag
+    }
+
+    /// Add a block, adding its state to the palettes if it is not already present in
+    /// the first one. New states are added to every palette to keep them the same length.
+    pub fn set_block(&mut self, pos: [i32; 3], state: BlockState, nbt: Option<Tag>) {
+        if self.palettes.is_empty() {
+            self.palettes.push(Vec::new());
+        }
+        let index = match self.palettes[0].iter().position(|existing| *existing == state) {
+            Some(index) => index,
+            None => {
+                for palette in &mut self.palettes {
+                    palette.push(state.clone());
+                }
+                self.palettes[0].len() - 1
+            },
+        };
+        self.blocks.retain(|block| block.pos != pos);
+        self.blocks.push(StructureBlock { pos, state: index, nbt });
+    }
+
+    /// Blocks with their states looked up in the first palette
+    pub fn blocks<'a>(&'a self) -> impl Iterator<Item = ResolvedBlock<'a>> + 'a {
+        self.blocks_with_palette(0)
+    }
+
+    /// Blocks with their states looked up in the given palette
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is no palette with the given index, or if `palettes` was
+    /// changed so that a block refers to a state outside of it
+    pub fn blocks_with_palette<'a>(&'a self, palette: usize) -> impl Iterator<Item = ResolvedBlock<'a>> + 'a {
+        let palette = &self.palettes[palette];
+        self.blocks.iter().map(move |block| ResolvedBlock {
+            pos: block.pos,
+            state: &palette[block.state],
+            nbt: block.nbt.as_ref(),
+        })
+    }
+}
+
+fn palette(states: &[Tag]) -> Result<Vec<BlockState>> {
+    states.iter().map(BlockState::from_tag).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_structure() {
+        let mut structure = Structure::new([2, 1, 1]);
+        structure.data_version = Some(3465);
+        let chest = BlockState::new("minecraft:chest").with_property("facing", "west");
+        structure.set_block([0, 0, 0], BlockState::new("minecraft:stone"), None);
+        structure.set_block([1, 0, 0], chest.clone(), Some(Tag::compound(vec![("id", Tag::from("minecraft:chest"))])));
+        structure.entities.push(StructureEntity {
+            pos: [0.5, 1.0, 0.5],
+            block_pos: [0, 1, 0],
+            nbt: Tag::compound(vec![("id", Tag::from("minecraft:pig"))]),
+        });
+
+        let mut written = Vec::new();
+        structure.write(&mut written).unwrap();
+        let reread = Structure::read(&mut Cursor::new(written)).unwrap();
+        assert_eq!(reread, structure);
+
+        let blocks: Vec<_> = reread.blocks().collect();
+        assert_eq!(blocks[1].state, &chest);
+        assert_eq!(blocks[1].nbt.and_then(|nbt| nbt.get("id")), Some(&Tag::from("minecraft:chest")));
+
+        // Blocks must refer to a state within every palette
+        let mut tag = structure.to_tag();
+        tag.remove("palette");
+        tag.insert("palettes", Tag::List(vec![Tag::List(vec![BlockState::air().to_tag()])]));
+        assert!(Structure::from_tag(&tag).is_err());
+        tag.insert("palettes", Tag::List(Vec::new()));
+        assert!(Structure::from_tag(&tag).is_err());
+        let states = |names: &[&str]| Tag::List(names.iter().map(|&name| BlockState::new(name).to_tag()).collect());
+        tag.insert("palettes", Tag::List(vec![states(&["minecraft:stone", "minecraft:chest"]), states(&["minecraft:air"])]));
+        assert!(Structure::from_tag(&tag).is_err());
+
+        // New states are added to every palette
+        tag.insert("palettes", Tag::List(vec![
+            states(&["minecraft:stone", "minecraft:chest"]),
+            states(&["minecraft:cobblestone", "minecraft:barrel"]),
+        ]));
+        let mut structure = Structure::from_tag(&tag).unwrap();
+        structure.set_block([0, 0, 1], BlockState::new("minecraft:glass"), None);
+        assert_eq!(structure.blocks_with_palette(1).last().map(|block| block.state), Some(&BlockState::new("minecraft:glass")));
+        assert_eq!(Structure::from_tag(&structure.to_tag()).unwrap(), structure);
+
+        let mut empty = Structure::new([1, 1, 1]);
+        empty.palettes.clear();
+        assert_eq!(Structure::from_tag(&empty.to_tag()).unwrap(), Structure::new([1, 1, 1]));
+    }
+}
@@ -1,20 +1,30 @@
 pub use byteorder::LittleEndian as PocketEndianness;
 
 pub use byteorder::BigEndian as DesktopEndianness;
+use std::io;
 use std::str::FromStr;
 use errors;
+use uuid::Uuid;
 
 #[derive(Debug)]
 /// The NBT specification accepts three different compression formats:
 /// - none
 /// - gzip
 /// - zlib
+///
+/// Minecraft additionally stores chunks using the LZ4 block stream written by lz4-java,
+/// and LZ4 frames as written by the reference `lz4` tool are supported for convenience.
 pub enum Compression {
     None,
     Gzip ,
     Zlib ,
+    Lz4 ,
+    Lz4Frame ,
 }
 
+/// Number of bytes `Compression::detect` needs to identify any supported format
+pub const DETECT_LENGTH: usize = 9;
+
 impl Compression {
     /// Get a human-readable name of the compression algorithm used
     pub fn to_str(&self) -> &str {
@@ -22,11 +32,13 @@ impl Compression {
             Compression::None => "None",
             Compression::Gzip => "Gzip",
             Compression::Zlib => "Zlib",
+            Compression::Lz4 => "Lz4",
+            Compression::Lz4Frame => "Lz4Frame",
         }
     }
 
     /// Parse the compression header byte to determine if our file
-    /// has been deflated at all
+    /// has been deflated at all, LZ4 can only be identified by `detect`
     pub fn from_byte(value: u8) -> Option<Self> {
         match value {
             0x0a => Some(Compression::None),
@@ -35,6 +47,22 @@ impl Compression {
             _ => None
         }
     }
+
+    /// Identify the compression of a file from its first `DETECT_LENGTH` bytes,
+    /// or all of them for shorter files
+    pub fn detect(header: &[u8]) -> errors::Result<Self> {
+        match *header {
+            [] => Err(errors::Error::Io(io::ErrorKind::UnexpectedEof.into())),
+            [0x1f, 0x8b, ..] => Ok(Compression::Gzip),
+            [0x04, 0x22, 0x4d, 0x18, ..] => Ok(Compression::Lz4Frame),
+            _ if header.starts_with(::lz4::MAGIC) => Ok(Compression::Lz4),
+            // RFC 1950: deflate with a window of at most 32K and a valid header checksum
+            [cmf, flg, ..] if cmf & 0x0f == 8 && cmf >> 4 <= 7 && (u16::from(cmf) << 8 | u16::from(flg)) % 31 == 0 =>
+                Ok(Compression::Zlib),
+            [0x01..=0x0c, ..] => Ok(Compression::None),
+            [format_header, ..] => Err(errors::Error::InvalidCompressionFormat(format_header)),
+        }
+    }
 }
 
 impl FromStr for Compression {
@@ -45,7 +73,9 @@ impl FromStr for Compression {
             "Gzip" => Ok(Compression::Gzip),
             "Zlib" => Ok(Compression::Zlib),
             "None" => Ok(Compression::None),
-            _ => bail!("Invalid Compression type"),
+            "Lz4" => Ok(Compression::Lz4),
+            "Lz4Frame" => Ok(Compression::Lz4Frame),
+            _ => Err(errors::Error::UnknownCompression(s.to_owned())),
         }
     }
 }
@@ -123,6 +153,153 @@ impl Tag {
             Tag::LongArray(..) => 0x0c,
         }
     }
+
+    /// Build a Compound the way it would be read, terminated by an End entry
+    pub fn compound<S: Into<String>, I: IntoIterator<Item = (S, Tag)>>(entries: I) -> Self {
+        let mut entries: Vec<(String, Tag)> = entries.into_iter()
+            .map(|(name, tag)| (name.into(), tag))
+            .collect();
+        entries.push((String::new(), Tag::End));
+        Tag::Compound(entries)
+    }
+
+    /// Get the entry of a Compound with the given name
+    pub fn get(&self, name: &str) -> Option<&Tag> {
+        match *self {
+            Tag::Compound(ref entries) => entries.iter()
+                .find(|(key, tag)| key == name && *tag != Tag::End)
+                .map(|(_, tag)| tag),
+            _ => None,
+        }
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut Tag> {
+        match *self {
+            Tag::Compound(ref mut entries) => entries.iter_mut()
+                .find(|(key, tag)| key == name && *tag != Tag::End)
+                .map(|(_, tag)| tag),
+            _ => None,
+        }
+    }
+
+    /// Set the entry of a Compound with the given name, returning the previous value.
+    /// New entries are added before the End entry, if there is one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the tag is not a Compound
+    pub fn insert<S: Into<String>>(&mut self, name: S, tag: Tag) -> Option<Tag> {
+        let name = name.into();
+        if let Some(existing) = self.get_mut(&name) {
+            return Some(::std::mem::replace(existing, tag));
+        }
+        match *self {
+            Tag::Compound(ref mut entries) => {
+                let index = entries.iter().position(|(_, tag)| *tag == Tag::End).unwrap_or(entries.len());
+                entries.insert(index, (name, tag));
+                None
+            },
+            ref tag => panic!("Cannot insert into Tag::{}", tag.type_name()),
+        }
+    }
+
+    /// Remove the entry of a Compound with the given name
+    pub fn remove(&mut self, name: &str) -> Option<Tag> {
+        match *self {
+            Tag::Compound(ref mut entries) => entries.iter()
+                .position(|(key, tag)| key == name && *tag != Tag::End)
+                .map(|index| entries.remove(index).1),
+            _ => None,
+        }
+    }
+
+    /// Entries of a Compound, excluding its End entry
+    pub fn entries(&self) -> Option<impl Iterator<Item = (&str, &Tag)>> {
+        match *self {
+            Tag::Compound(ref entries) => Some(entries.iter()
+                .filter(|(_, tag)| *tag != Tag::End)
+                .map(|(name, tag)| (name.as_str(), tag))),
+            _ => None,
+        }
+    }
+
+    /// The value of any integer tag, as Minecraft converts between numeric types freely
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            Tag::Byte(value) => Some(value.into()),
+            Tag::Short(value) => Some(value.into()),
+            Tag::Int(value) => Some(value.into()),
+            Tag::Long(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// The value of a Byte, Short or Int tag
+    pub fn as_i32(&self) -> Option<i32> {
+        match *self {
+            Tag::Byte(value) => Some(value.into()),
+            Tag::Short(value) => Some(value.into()),
+            Tag::Int(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// The value of any numeric tag
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            Tag::Float(value) => Some(value.into()),
+            Tag::Double(value) => Some(value),
+            ref tag => tag.as_i64().map(|value| value as f64),
+        }
+    }
+
+    /// The value of a Byte, where anything but zero is true
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            Tag::Byte(value) => Some(value != 0),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match *self {
+            Tag::String(ref value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_list(&self) -> Option<&[Tag]> {
+        match *self {
+            Tag::List(ref elements) => Some(elements),
+            _ => None,
+        }
+    }
+
+    pub fn as_byte_array(&self) -> Option<&[i8]> {
+        match *self {
+            Tag::ByteArray(ref values) => Some(values),
+            _ => None,
+        }
+    }
+
+    pub fn as_int_array(&self) -> Option<&[i32]> {
+        match *self {
+            Tag::IntArray(ref values) => Some(values),
+            _ => None,
+        }
+    }
+
+    /// A UUID stored as four Ints or as a String, see `uuid::Uuid::from_tag`
+    pub fn as_uuid(&self) -> Option<Uuid> {
+        Uuid::from_tag(self)
+    }
+
+    pub fn as_long_array(&self) -> Option<&[i64]> {
+        match *self {
+            Tag::LongArray(ref values) => Some(values),
+            _ => None,
+        }
+    }
 }
 
 // region impl From for Tag
@@ -163,4 +340,22 @@ impl From<f64> for Tag {
     }
 }
 
+impl From<bool> for Tag {
+    fn from(val: bool) -> Self {
+        Tag::Byte(i8::from(val))
+    }
+}
+
+impl From<String> for Tag {
+    fn from(val: String) -> Self {
+        Tag::String(val)
+    }
+}
+
+impl<'a> From<&'a str> for Tag {
+    fn from(val: &'a str) -> Self {
+        Tag::String(val.to_owned())
+    }
+}
+
 // endregion impl From for Tag
\ No newline at end of file
@@ -0,0 +1,122 @@
+//! Conversion of text components between their JSON form and the NBT form used by
+//! Minecraft for chat since 1.20.3, and for item names and lore since 1.21.5.
+//!
+//! A component which only holds text may be written as a plain string on either side.
+//! As List tags can only hold elements of a single type, lists such as `extra` and
+//! `with` which mix strings and components have every element which is not a Compound
+//! wrapped in a Compound under an empty name, `"a"` becoming `{"": "a"}`, in the same
+//! way as `json::from_plain` does.
+use serde_json::Map;
+use serde_json::Value;
+use errors::*;
+
+use json::number_tag;
+use json::wrap_elements;
+use json::wrapped;
+use tag::Tag;
+
+/// Style properties holding booleans, which are Bytes in NBT
+const BOOLEAN_KEYS: &[&str] = &["bold", "italic", "underlined", "strikethrough", "obfuscated", "interpret"];
+
+/// Convert a component from its JSON form into NBT
+pub fn to_nbt(component: &Value) -> Result<Tag> {
+    Ok(match *component {
+        Value::Null => return Err(Error::InvalidJson(String::from("text components cannot be null"))),
+        Value::Bool(value) => Tag::Byte(i8::from(value)),
+        Value::Number(ref number) => number_tag(number),
+        Value::String(ref text) => Tag::String(text.clone()),
+        Value::Array(ref elements) => {
+            let mut list = Vec::with_capacity(elements.len());
+            for element in elements {
+                list.push(to_nbt(element)?);
+            }
+            wrap_elements(list)
+        },
+        Value::Object(ref object) => {
+            let mut entries = Vec::with_capacity(object.len() + 1);
+            for (name, value) in object.iter().filter(|(_, value)| !value.is_null()) {
+                entries.push((name.clone(), to_nbt(value)?));
+            }
+            entries.push((String::new(), Tag::End));
+            Tag::Compound(entries)
+        },
+    })
+}
+
+/// Convert a component from NBT into its JSON form
+pub fn from_nbt(component: &Tag) -> Result<Value> {
+    Ok(match *component {
+        Tag::End => return Err(Error::UnexpectedElement { container: "text component", element_type: 0x00 }),
+        Tag::Byte(value) => Value::from(value),
+        Tag::Short(value) => Value::from(value),
+        Tag::Int(value) => Value::from(value),
+        Tag::Long(value) => Value::from(value),
+        Tag::Float(value) => Value::from(f64::from(value)),
+        Tag::Double(value) => Value::from(value),
+        Tag::ByteArray(ref values) => Value::from(values.clone()),
+        Tag::String(ref text) => Value::from(text.as_str()),
+        Tag::List(ref elements) => {
+            let mut array = Vec::with_capacity(elements.len());
+            for element in elements {
+                let element = match *element {
+                    Tag::Compound(ref entries) => wrapped(entries).unwrap_or(element),
+                    ref element => element,
+                };
+                array.push(from_nbt(element)?);
+            }
+            Value::Array(array)
+        },
+        Tag::Compound(ref entries) => {
+            let mut object = Map::new();
+            for (name, tag) in entries.iter().filter(|(_, tag)| *tag != Tag::End) {
+                let value = match *tag {
+                    Tag::Byte(value) if BOOLEAN_KEYS.contains(&name.as_str()) => Value::Bool(value != 0),
+                    ref tag => from_nbt(tag)?,
+                };
+                object.insert(name.clone(), value);
+            }
+            Value::Object(object)
+        },
+        Tag::IntArray(ref values) => Value::from(values.clone()),
+        Tag::LongArray(ref values) => Value::from(values.clone()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_components() {
+        let json: Value = r#"{"text": "Hello ", "bold": true, "color": "gold",
+            "extra": ["world", {"translate": "chat.type.text", "with": [1, "two"]}, {"": "x"}],
+            "hoverEvent": {"action": "show_text", "contents": "tip"}}"#.parse().unwrap();
+        let tag = to_nbt(&json).unwrap();
+        let extra = match tag {
+            Tag::Compound(ref entries) => {
+                assert_eq!(entries[1], (String::from("bold"), Tag::Byte(1)));
+                entries.iter().find(|(name, _)| name == "extra").map(|(_, tag)| tag).unwrap()
+            },
+            _ => panic!("Expected Tag::Compound, got Tag::{}", tag.type_name()),
+        };
+        match *extra {
+            Tag::List(ref elements) => {
+                assert!(elements.iter().all(|element| element.type_id() == 0x0a));
+                assert_eq!(elements[0], Tag::Compound(vec![
+                    (String::new(), Tag::String(String::from("world"))),
+                    (String::new(), Tag::End),
+                ]));
+            },
+            _ => panic!("Expected Tag::List, got Tag::{}", extra.type_name()),
+        }
+        assert_eq!(from_nbt(&tag).unwrap(), json);
+
+        // Plain strings are valid components on both sides
+        assert_eq!(to_nbt(&Value::from("hi")).unwrap(), Tag::String(String::from("hi")));
+        assert_eq!(from_nbt(&Tag::String(String::from("hi"))).unwrap(), Value::from("hi"));
+        for lore in &[r#"["a", "b"]"#, r#"[{"": "a"}, {"": "b"}]"#] {
+            let lore: Value = lore.parse().unwrap();
+            assert_eq!(from_nbt(&to_nbt(&lore).unwrap()).unwrap(), lore);
+        }
+    }
+}
@@ -0,0 +1,200 @@
+//! UUIDs of players and entities in the encodings Minecraft has used for them.
+//!
+//! Since 1.16 a UUID is stored as an IntArray of four Ints, most significant first.
+//! Older data stores it as two Longs named `<name>Most` and `<name>Least`, and some
+//! entries such as `Owner` of a skull hold it as a hyphenated String. In SNBT it is
+//! written as an IntArray or, since 1.21.5, as `uuid("<hyphenated>")`. With the `uuid`
+//! feature enabled `Uuid` converts to and from `uuid::Uuid`.
+use std::fmt;
+use std::str::FromStr;
+use errors::*;
+
+use tag::Tag;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Uuid(pub u128);
+
+impl Uuid {
+    pub fn from_int_array(ints: [i32; 4]) -> Self {
+        Uuid(ints.iter().fold(0, |uuid, &int| uuid << 32 | u128::from(int as u32)))
+    }
+
+    pub fn to_int_array(self) -> [i32; 4] {
+        [(self.0 >> 96) as i32, (self.0 >> 64) as i32, (self.0 >> 32) as i32, self.0 as i32]
+    }
+
+    pub fn from_most_least(most: i64, least: i64) -> Self {
+        Uuid(u128::from(most as u64) << 64 | u128::from(least as u64))
+    }
+
+    pub fn to_most_least(self) -> (i64, i64) {
+        ((self.0 >> 64) as i64, self.0 as i64)
+    }
+
+    /// Read a UUID stored as four Ints or as a String
+    pub fn from_tag(tag: &Tag) -> Option<Self> {
+        match *tag {
+            Tag::IntArray(ref ints) if ints.len() == 4 => Some(Uuid::from_int_array([ints[0], ints[1], ints[2], ints[3]])),
+            Tag::String(ref uuid) => uuid.parse().ok(),
+            _ => None,
+        }
+    }
+
+    /// The IntArray used since 1.16
+    pub fn to_tag(self) -> Tag {
+        Tag::IntArray(self.to_int_array().to_vec())
+    }
+
+    /// The SNBT of the IntArray, `[I; a, b, c, d]`
+    pub fn to_snbt(self) -> String {
+        let [a, b, c, d] = self.to_int_array();
+        format!("[I; {}, {}, {}, {}]", a, b, c, d)
+    }
+
+    /// Parse SNBT holding a UUID, either an IntArray of four Ints or `uuid(...)` with
+    /// the hyphenated form, quoted or not
+    pub fn from_snbt(snbt: &str) -> Result<Self> {
+        let invalid = || Error::InvalidTag { name: snbt.to_owned(), expected: "a UUID in SNBT" };
+        let snbt = snbt.trim();
+        if let Some(argument) = snbt.strip_prefix("uuid(").and_then(|rest| rest.strip_suffix(')')) {
+            let argument = argument.trim();
+            let unquoted = ["\"", "'"].iter()
+                .filter_map(|quote| argument.strip_prefix(quote)?.strip_suffix(quote))
+                .next()
+                .unwrap_or(argument);
+            return unquoted.parse().map_err(|_| invalid());
+        }
+
+        let elements = snbt.strip_prefix('[')
+            .and_then(|rest| rest.strip_suffix(']'))
+            .and_then(|rest| rest.trim_start().strip_prefix("I;"))
+            .ok_or_else(invalid)?;
+        let ints: Vec<i32> = elements.split(',')
+            .map(|int| int.trim().parse())
+            .collect::<::std::result::Result<_, _>>()
+            .map_err(|_| invalid())?;
+        match ints[..] {
+            [a, b, c, d] => Ok(Uuid::from_int_array([a, b, c, d])),
+            _ => Err(invalid()),
+        }
+    }
+
+    /// Read the UUID named `name` from a Compound in any of its encodings, trying
+    /// the Longs named `<name>Most` and `<name>Least` when it is missing
+    pub fn from_compound(compound: &Tag, name: &str) -> Option<Self> {
+        if let Some(tag) = compound.get(name) {
+            return Uuid::from_tag(tag);
+        }
+        let most = compound.get(&format!("{}Most", name))?.as_i64()?;
+        let least = compound.get(&format!("{}Least", name))?.as_i64()?;
+        Some(Uuid::from_most_least(most, least))
+    }
+
+    /// Store the UUID as the Longs named `<name>Most` and `<name>Least`, as before 1.16
+    pub fn insert_most_least(self, compound: &mut Tag, name: &str) {
+        let (most, least) = self.to_most_least();
+        compound.insert(format!("{}Most", name), Tag::Long(most));
+        compound.insert(format!("{}Least", name), Tag::Long(least));
+    }
+}
+
+impl fmt::Display for Uuid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let hex = format!("{:032x}", self.0);
+        write!(f, "{}-{}-{}-{}-{}", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..])
+    }
+}
+
+impl FromStr for Uuid {
+    type Err = Error;
+
+    /// Parse the hyphenated form, or the 32 digits without hyphens used by the Mojang API
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || Error::InvalidTag { name: s.to_owned(), expected: "a UUID" };
+        let groups: Vec<&str> = s.split('-').collect();
+        let hyphenated = groups.iter().map(|group| group.len()).eq([8, 4, 4, 4, 12].iter().cloned());
+        if !(hyphenated || groups.len() == 1 && s.len() == 32) {
+            return Err(invalid());
+        }
+        let digits: String = groups.concat();
+        if !digits.bytes().all(|digit| digit.is_ascii_hexdigit()) {
+            return Err(invalid());
+        }
+        u128::from_str_radix(&digits, 16).map(Uuid).map_err(|_| invalid())
+    }
+}
+
+impl From<Uuid> for Tag {
+    fn from(uuid: Uuid) -> Self {
+        uuid.to_tag()
+    }
+}
+
+#[cfg(feature = "uuid")]
+impl From<::uuid_crate::Uuid> for Uuid {
+    fn from(uuid: ::uuid_crate::Uuid) -> Self {
+        Uuid(uuid.as_u128())
+    }
+}
+
+#[cfg(feature = "uuid")]
+impl From<Uuid> for ::uuid_crate::Uuid {
+    fn from(uuid: Uuid) -> Self {
+        ::uuid_crate::Uuid::from_u128(uuid.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_uuid() {
+        let uuid: Uuid = "069a79f4-44e9-4726-a5be-fca90e38aaf5".parse().unwrap();
+        assert_eq!(uuid.to_string(), "069a79f4-44e9-4726-a5be-fca90e38aaf5");
+        assert_eq!("069a79f444e94726a5befca90e38aaf5".parse::<Uuid>().unwrap(), uuid);
+        assert!("069a79f4-44e9-4726-a5be".parse::<Uuid>().is_err());
+        assert!("069a79f4-44e9-4726-a5be-fca90e38aafg".parse::<Uuid>().is_err());
+        assert!("+69a79f444e94726a5befca90e38aaf5".parse::<Uuid>().is_err());
+
+        assert_eq!(uuid.to_int_array(), [0x069a79f4, 0x44e94726, 0xa5befca9u32 as i32, 0x0e38aaf5]);
+        assert_eq!(Uuid::from_int_array(uuid.to_int_array()), uuid);
+        let (most, least) = uuid.to_most_least();
+        assert_eq!(most, 0x069a79f4_44e94726);
+        assert_eq!(Uuid::from_most_least(most, least), uuid);
+
+        let mut legacy = Tag::compound(vec![("Name", Tag::from("Notch"))]);
+        uuid.insert_most_least(&mut legacy, "UUID");
+        assert_eq!(Uuid::from_compound(&legacy, "UUID"), Some(uuid));
+        let current = Tag::compound(vec![("UUID", Tag::from(uuid)), ("Owner", Tag::from(uuid.to_string()))]);
+        assert_eq!(Uuid::from_compound(&current, "UUID"), Some(uuid));
+        assert_eq!(Uuid::from_compound(&current, "Owner"), Some(uuid));
+        assert_eq!(Uuid::from_compound(&current, "Missing"), None);
+    }
+
+    #[test]
+    fn test_snbt() {
+        let uuid: Uuid = "069a79f4-44e9-4726-a5be-fca90e38aaf5".parse().unwrap();
+        assert_eq!(uuid.to_snbt(), "[I; 110787060, 1156138790, -1514210135, 238594805]");
+        assert_eq!(Uuid::from_snbt(&uuid.to_snbt()).unwrap(), uuid);
+        assert_eq!(Uuid::from_snbt("[I;110787060,1156138790,-1514210135,238594805]").unwrap(), uuid);
+        assert_eq!(Uuid::from_snbt("uuid(\"069a79f4-44e9-4726-a5be-fca90e38aaf5\")").unwrap(), uuid);
+        assert_eq!(Uuid::from_snbt("uuid('069a79f4-44e9-4726-a5be-fca90e38aaf5')").unwrap(), uuid);
+        assert_eq!(Uuid::from_snbt(" uuid( 069a79f4-44e9-4726-a5be-fca90e38aaf5 ) ").unwrap(), uuid);
+
+        assert!(Uuid::from_snbt("[I; 1, 2, 3]").is_err());
+        assert!(Uuid::from_snbt("[L; 1, 2, 3, 4]").is_err());
+        assert!(Uuid::from_snbt("[I; 1, 2, 3, 2147483648]").is_err());
+        assert!(Uuid::from_snbt("uuid(\"069a79f4\")").is_err());
+        assert!(Uuid::from_snbt("uuid(\"069a79f4-44e9-4726-a5be-fca90e38aaf5')").is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "uuid")]
+    fn test_uuid_crate() {
+        let uuid: Uuid = "069a79f4-44e9-4726-a5be-fca90e38aaf5".parse().unwrap();
+        let converted = ::uuid_crate::Uuid::from(uuid);
+        assert_eq!(converted.to_string(), uuid.to_string());
+        assert_eq!(Uuid::from(converted), uuid);
+    }
+}
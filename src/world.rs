@@ -0,0 +1,290 @@
+//! The save folder of a Java Edition world.
+//!
+//! A world holds its `level.dat` and the data of its players at the top, while the
+//! chunks of each dimension are spread over the region files of its `region`,
+//! `entities` and `poi` folders. Files are only opened when they are asked for.
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::BufReader;
+use std::path::Path;
+use std::path::PathBuf;
+use serde_json::Value;
+use errors::*;
+
+use level::LevelData;
+use read::read_file;
+use read::NBTFile;
+use region::RegionFile;
+use region::REGION_CHUNKS;
+use tag::Tag;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Dimension {
+    Overworld,
+    Nether,
+    End,
+    /// A dimension added by a data pack, by its namespaced id such as `example:moon`
+    Custom(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// The kinds of region files stored for every dimension
+pub enum RegionKind {
+    /// Blocks, biomes and block entities in `region`
+    Chunks,
+    /// Entities in `entities`, since 1.17
+    Entities,
+    /// Points of interest such as beds and workstations in `poi`
+    Poi,
+}
+
+impl Dimension {
+    /// Look up a dimension by its namespaced id, such as `minecraft:the_nether`
+    pub fn from_id(id: &str) -> Self {
+        match id {
+            "minecraft:overworld" => Dimension::Overworld,
+            "minecraft:the_nether" => Dimension::Nether,
+            "minecraft:the_end" => Dimension::End,
+            id => Dimension::Custom(id.to_owned()),
+        }
+    }
+
+    pub fn id(&self) -> &str {
+        match *self {
+            Dimension::Overworld => "minecraft:overworld",
+            Dimension::Nether => "minecraft:the_nether",
+            Dimension::End => "minecraft:the_end",
+            Dimension::Custom(ref id) => id,
+        }
+    }
+
+    /// Look up a dimension by the number used before 1.16
+    pub fn from_legacy(number: i32) -> Option<Self> {
+        match number {
+            0 => Some(Dimension::Overworld),
+            -1 => Some(Dimension::Nether),
+            1 => Some(Dimension::End),
+            _ => None,
+        }
+    }
+
+    /// The number used for the dimension before 1.16
+    pub fn legacy(&self) -> Option<i32> {
+        match *self {
+            Dimension::Overworld => Some(0),
+            Dimension::Nether => Some(-1),
+            Dimension::End => Some(1),
+            Dimension::Custom(..) => None,
+        }
+    }
+
+    /// Folder of the dimension relative to the world
+    pub fn path(&self) -> PathBuf {
+        match *self {
+            Dimension::Overworld => PathBuf::new(),
+            Dimension::Nether => PathBuf::from("DIM-1"),
+            Dimension::End => PathBuf::from("DIM1"),
+            Dimension::Custom(ref id) => {
+                let (namespace, path) = id.split_once(':').unwrap_or(("minecraft", id));
+                Path::new("dimensions").join(namespace).join(path)
+            },
+        }
+    }
+}
+
+impl RegionKind {
+    pub fn folder(self) -> &'static str {
+        match self {
+            RegionKind::Chunks => "region",
+            RegionKind::Entities => "entities",
+            RegionKind::Poi => "poi",
+        }
+    }
+}
+
+/// Read an NBT file, or `None` if it does not exist
+fn read_optional(path: &Path) -> Result<Option<NBTFile>> {
+    match File::open(path) {
+        Ok(file) => read_file(&mut BufReader::new(file)).map(Some),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Names of the files in a folder with the given extension, without the extension
+fn file_stems(path: &Path, extension: &str) -> Result<Vec<String>> {
+    let entries = match fs::read_dir(path) {
+        Ok(entries) => entries,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut stems = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension().is_some_and(|found| found == extension) {
+            if let Some(stem) = path.file_stem().and_then(|stem| stem.to_str()) {
+                stems.push(stem.to_owned());
+            }
+        }
+    }
+    stems.sort();
+    Ok(stems)
+}
+
+#[derive(Debug, Clone)]
+pub struct World {
+    path: PathBuf,
+}
+
+impl World {
+    /// Open the save folder at `path`, which must hold a `level.dat`
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref().to_owned();
+        if !path.join("level.dat").is_file() {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "level.dat not found").into());
+        }
+        Ok(World { path })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn level_dat(&self) -> Result<NBTFile> {
+        read_file(&mut BufReader::new(File::open(self.path.join("level.dat"))?))
+    }
+
+    /// The typed contents of `level.dat`
+    pub fn level_data(&self) -> Result<LevelData> {
+        LevelData::from_file(&self.level_dat()?)
+    }
+
+    /// Folder holding the region files of a kind for a dimension
+    pub fn region_folder(&self, dimension: &Dimension, kind: RegionKind) -> PathBuf {
+        self.path.join(dimension.path()).join(kind.folder())
+    }
+
+    /// Path of the region file holding the region at region coordinates
+    pub fn region_path(&self, dimension: &Dimension, kind: RegionKind, x: i32, z: i32) -> PathBuf {
+        self.region_folder(dimension, kind).join(format!("r.{}.{}.mca", x, z))
+    }
+
+    /// Region coordinates of every region file of a kind for a dimension
+    pub fn regions(&self, dimension: &Dimension, kind: RegionKind) -> Result<Vec<(i32, i32)>> {
+        let stems = file_stems(&self.region_folder(dimension, kind), "mca")?;
+        Ok(stems.iter()
+            .filter_map(|stem| {
+                let mut parts = stem.split('.');
+                match (parts.next(), parts.next(), parts.next(), parts.next()) {
+                    (Some("r"), Some(x), Some(z), None) => Some((x.parse().ok()?, z.parse().ok()?)),
+                    _ => None,
+                }
+            })
+            .collect())
+    }
+
+    /// Open a region file for reading, if it exists, along with its external chunks
+    pub fn region(&self, dimension: &Dimension, kind: RegionKind, x: i32, z: i32) -> Result<Option<RegionFile<File>>> {
+        match File::open(self.region_path(dimension, kind, x, z)) {
+            Ok(file) => Ok(Some(RegionFile::open(file)?.with_external_folder(self.region_folder(dimension, kind), x, z))),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Read the chunk at chunk coordinates from the region file holding it
+    pub fn chunk(&self, dimension: &Dimension, kind: RegionKind, x: i32, z: i32) -> Result<Option<Tag>> {
+        let shift = REGION_CHUNKS.trailing_zeros();
+        match self.region(dimension, kind, x >> shift, z >> shift)? {
+            Some(mut region) => region.read_chunk(x, z),
+            None => Ok(None),
+        }
+    }
+
+    /// UUIDs of every player with a file in `playerdata`
+    pub fn players(&self) -> Result<Vec<String>> {
+        file_stems(&self.path.join("playerdata"), "dat")
+    }
+
+    /// The data of a player by their hyphenated UUID
+    pub fn player_data(&self, uuid: &str) -> Result<Option<NBTFile>> {
+        read_optional(&self.path.join("playerdata").join(format!("{}.dat", uuid)))
+    }
+
+    /// Names of every file in `data`, such as `raids` or `map_0`
+    pub fn data_names(&self) -> Result<Vec<String>> {
+        file_stems(&self.path.join("data"), "dat")
+    }
+
+    /// A file of `data` by its name
+    pub fn data(&self, name: &str) -> Result<Option<NBTFile>> {
+        read_optional(&self.path.join("data").join(format!("{}.dat", name)))
+    }
+
+    /// The advancements of a player by their hyphenated UUID, which are stored as JSON
+    pub fn advancements(&self, uuid: &str) -> Result<Option<Value>> {
+        let path = self.path.join("advancements").join(format!("{}.json", uuid));
+        match File::open(path) {
+            Ok(file) => ::serde_json::from_reader(BufReader::new(file))
+                .map(Some)
+                .map_err(|e| Error::InvalidJson(e.to_string())),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs::OpenOptions;
+    use tag::Compression;
+    use write::write_file;
+
+    #[test]
+    fn test_world() {
+        let path = env::temp_dir().join(format!("nbt-world-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(path.join("DIM-1/region")).unwrap();
+        fs::create_dir_all(path.join("playerdata")).unwrap();
+        fs::create_dir_all(path.join("advancements")).unwrap();
+        assert!(World::open(&path).is_err());
+
+        let level = NBTFile::with_root(Compression::Gzip, Tag::compound(vec![
+            ("Data", Tag::compound(vec![
+                ("LevelName", Tag::from("Test")),
+                ("SpawnX", Tag::Int(0)), ("SpawnY", Tag::Int(64)), ("SpawnZ", Tag::Int(0)),
+            ])),
+        ]));
+        write_file(&mut File::create(path.join("level.dat")).unwrap(), &level).unwrap();
+        let player = NBTFile::with_root(Compression::Gzip, Tag::compound(vec![("Health", Tag::Float(20.0))]));
+        let uuid = "069a79f4-44e9-4726-a5be-fca90e38aaf5";
+        write_file(&mut File::create(path.join("playerdata").join(format!("{}.dat", uuid))).unwrap(), &player).unwrap();
+        fs::write(path.join("advancements").join(format!("{}.json", uuid)), r#"{"DataVersion": 3700}"#).unwrap();
+
+        let chunk = Tag::compound(vec![("xPos", Tag::Int(-33)), ("zPos", Tag::Int(5))]);
+        let file = OpenOptions::new().read(true).write(true).create(true).truncate(true)
+            .open(path.join("DIM-1/region/r.-2.0.mca")).unwrap();
+        RegionFile::create(file).unwrap().write_chunk(-33, 5, &chunk, Compression::Zlib).unwrap();
+
+        let world = World::open(&path).unwrap();
+        assert_eq!(world.level_dat().unwrap().root, level.root);
+        assert_eq!(world.level_data().unwrap().name, "Test");
+        assert_eq!(world.players().unwrap(), vec![uuid.to_owned()]);
+        assert_eq!(world.player_data(uuid).unwrap().map(|file| file.root), Some(player.root));
+        assert!(world.player_data("missing").unwrap().is_none());
+        assert_eq!(world.advancements(uuid).unwrap().unwrap()["DataVersion"], 3700);
+        assert_eq!(world.data_names().unwrap(), Vec::<String>::new());
+
+        assert_eq!(world.regions(&Dimension::Nether, RegionKind::Chunks).unwrap(), vec![(-2, 0)]);
+        assert_eq!(world.regions(&Dimension::Overworld, RegionKind::Chunks).unwrap(), vec![]);
+        assert_eq!(world.chunk(&Dimension::Nether, RegionKind::Chunks, -33, 5).unwrap(), Some(chunk));
+        assert_eq!(world.chunk(&Dimension::Nether, RegionKind::Chunks, -33, 6).unwrap(), None);
+        assert_eq!(world.chunk(&Dimension::Nether, RegionKind::Entities, -33, 5).unwrap(), None);
+        assert_eq!(Dimension::Custom(String::from("example:moon")).path(), Path::new("dimensions/example/moon"));
+
+        fs::remove_dir_all(&path).unwrap();
+    }
+}
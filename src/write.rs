@@ -0,0 +1,325 @@
+use std::io;
+use std::io::Write;
+use std::marker::PhantomData;
+use std::slice;
+use byteorder::ByteOrder;
+use byteorder::WriteBytesExt;
+use errors::*;
+
+use read::NBTFile;
+use tag::Tag;
+use tag::Compression;
+use tag::DesktopEndianness;
+use tag::PocketEndianness;
+
+/// Write a complete NBT file, compressing it as described by `file.compression`.
+///
+/// The root of an `NBTFile` is a Compound holding the named root tag(s) of the file,
+/// these are written back to back without a closing End tag.
+pub fn write_file<W: Write>(writer: &mut W, file: &NBTFile) -> Result<()> {
+    if let Some(storage_version) = file.storage_version {
+        return write_bedrock_file(writer, storage_version, file);
+    }
+
+    match file.compression {
+        Compression::Gzip => {
+            let mut encoder = ::flate2::write::GzEncoder::new(writer, ::flate2::Compression::default());
+            write_root(&mut NbtWriter::new(&mut encoder), &file.root)?;
+            encoder.finish()?;
+        },
+        Compression::Zlib => {
+            let mut encoder = ::flate2::write::ZlibEncoder::new(writer, ::flate2::Compression::default());
+            write_root(&mut NbtWriter::new(&mut encoder), &file.root)?;
+            encoder.finish()?;
+        },
+        Compression::Lz4 => {
+            let mut encoder = ::lz4::Lz4BlockWriter::new(writer);
+            write_root(&mut NbtWriter::new(&mut encoder), &file.root)?;
+            encoder.finish()?;
+        },
+        Compression::Lz4Frame => {
+            let mut encoder = ::lz4_flex::frame::FrameEncoder::new(writer);
+            write_root(&mut NbtWriter::new(&mut encoder), &file.root)?;
+            encoder.finish().map_err(io::Error::from)?;
+        },
+        Compression::None => write_root(&mut NbtWriter::new(writer), &file.root)?,
+    }
+    Ok(())
+}
+
+/// Write a Bedrock `level.dat`, the little-endian payload is preceded by the storage
+/// version and the length of the payload, which is recomputed from `file.root`
+fn write_bedrock_file<W: Write>(writer: &mut W, storage_version: i32, file: &NBTFile) -> Result<()> {
+    match file.compression {
+        Compression::None => {},
+        _ => return Err(Error::UnsupportedFormat("compressed Bedrock level.dat")),
+    }
+
+    let mut payload = Vec::new();
+    write_root(&mut NbtWriter::<_, PocketEndianness>::with_byte_order(&mut payload), &file.root)?;
+    if payload.len() > u32::MAX as usize {
+        return Err(Error::CollectionTooLong(payload.len()));
+    }
+
+    writer.write_i32::<PocketEndianness>(storage_version)?;
+    writer.write_u32::<PocketEndianness>(payload.len() as u32)?;
+    writer.write_all(&payload)?;
+    Ok(())
+}
+
+fn write_root<W: Write, E: ByteOrder>(writer: &mut NbtWriter<W, E>, root: &Tag) -> Result<()> {
+    match *root {
+        Tag::Compound(ref entries) => {
+            for (name, tag) in entries.iter().filter(|(_, tag)| *tag != Tag::End) {
+                writer.write_named_tag(name, tag)?;
+            }
+            Ok(())
+        },
+        ref tag => Err(Error::UnexpectedElement { container: "NBTFile", element_type: tag.type_id() }),
+    }
+}
+
+/// Write the type, name and payload of a tag as found within a Compound
+pub fn write_named_tag<W: Write>(writer: &mut W, name: &str, tag: &Tag) -> Result<()> {
+    NbtWriter::new(writer).write_named_tag(name, tag)
+}
+
+/// Write the payload of a tag
+pub fn write_tag<W: Write>(writer: &mut W, tag: &Tag) -> Result<()> {
+    NbtWriter::new(writer).write_tag(tag)
+}
+
+/// Writes tags to an underlying writer, the counterpart to `NbtReader`.
+///
+/// Java Edition data is big-endian, Bedrock Edition data can be written using a writer
+/// created by `NbtWriter::<_, PocketEndianness>::with_byte_order`.
+pub struct NbtWriter<W, E = DesktopEndianness> {
+    inner: W,
+    byte_order: PhantomData<E>,
+}
+
+impl<W: Write> NbtWriter<W> {
+    pub fn new(inner: W) -> Self {
+        NbtWriter::with_byte_order(inner)
+    }
+}
+
+impl<W: Write, E: ByteOrder> NbtWriter<W, E> {
+    pub fn with_byte_order(inner: W) -> Self {
+        NbtWriter {
+            inner,
+            byte_order: PhantomData,
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+
+    /// Write the type, name and payload of a tag as found within a Compound
+    pub fn write_named_tag(&mut self, name: &str, tag: &Tag) -> Result<()> {
+        self.inner.write_u8(tag.type_id())?;
+        self.write_string(name)?;
+        self.write_tag(tag)
+    }
+
+    /// Write the payload of a tag.
+    ///
+    /// Lists and Compounds are written using an explicit stack rather than recursion,
+    /// so arbitrarily deep structures can be written regardless of the call stack size.
+    pub fn write_tag(&mut self, tag: &Tag) -> Result<()> {
+        let mut stack = Vec::new();
+        if let Some(frame) = self.write_payload(tag)? {
+            stack.push(frame);
+        }
+
+        while let Some(next) = stack.last_mut().map(Frame::next) {
+            let child = match next {
+                Some(child) => child,
+                None => {
+                    if let Some(Frame::Compound(..)) = stack.pop() {
+                        self.inner.write_u8(0x00)?;
+                    }
+                    continue;
+                },
+            };
+
+            if let Some(name) = child.name {
+                self.inner.write_u8(child.tag.type_id())?;
+                self.write_string(name)?;
+            }
+
+            if let Some(frame) = self.write_payload(child.tag)? {
+                stack.push(frame);
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the payload of `tag`, returning a frame for the contents of Lists and Compounds
+    fn write_payload<'a>(&mut self, tag: &'a Tag) -> Result<Option<Frame<'a>>> {
+        match *tag {
+            Tag::End => {},
+            Tag::Byte(value) => self.inner.write_i8(value)?,
+            Tag::Short(value) => self.inner.write_i16::<E>(value)?,
+            Tag::Int(value) => self.inner.write_i32::<E>(value)?,
+            Tag::Long(value) => self.inner.write_i64::<E>(value)?,
+            Tag::Float(value) => self.inner.write_f32::<E>(value)?,
+            Tag::Double(value) => self.inner.write_f64::<E>(value)?,
+            Tag::ByteArray(ref values) => {
+                self.write_length(values.len())?;
+                for &value in values {
+                    self.inner.write_i8(value)?;
+                }
+            },
+            Tag::String(ref value) => self.write_string(value)?,
+            Tag::List(ref elements) => {
+                let element_type = elements.first().map_or(0x00, Tag::type_id);
+                if let Some(element) = elements.iter().find(|element| element.type_id() != element_type) {
+                    return Err(Error::UnexpectedElement { container: "List", element_type: element.type_id() })
+                }
+                self.inner.write_u8(element_type)?;
+                self.write_length(elements.len())?;
+                return Ok(Some(Frame::List(elements.iter())));
+            },
+            Tag::Compound(ref entries) => return Ok(Some(Frame::Compound(entries.iter()))),
+            Tag::IntArray(ref values) => {
+                self.write_length(values.len())?;
+                for &value in values {
+                    self.inner.write_i32::<E>(value)?;
+                }
+            },
+            Tag::LongArray(ref values) => {
+                self.write_length(values.len())?;
+                for &value in values {
+                    self.inner.write_i64::<E>(value)?;
+                }
+            },
+        }
+        Ok(None)
+    }
+
+    fn write_length(&mut self, length: usize) -> Result<()> {
+        if length > i32::MAX as usize {
+            return Err(Error::CollectionTooLong(length))
+        }
+        self.inner.write_i32::<E>(length as i32)?;
+        Ok(())
+    }
+
+    fn write_string(&mut self, value: &str) -> Result<()> {
+        // NBT strings are prefixed with an *unsigned* 16-bit length, see `read_string`
+        if value.len() > u16::MAX as usize {
+            return Err(Error::StringTooLong(value.len()))
+        }
+        self.inner.write_u16::<E>(value.len() as u16)?;
+        self.inner.write_all(value.as_bytes())?;
+        Ok(())
+    }
+}
+
+/// A List or Compound which is still being written by `NbtWriter::write_tag`
+enum Frame<'a> {
+    Compound(slice::Iter<'a, (String, Tag)>),
+    List(slice::Iter<'a, Tag>),
+}
+
+struct Child<'a> {
+    name: Option<&'a str>,
+    tag: &'a Tag,
+}
+
+impl<'a> Frame<'a> {
+    fn next(&mut self) -> Option<Child<'a>> {
+        match *self {
+            Frame::Compound(ref mut entries) => entries
+                .find(|(_, tag)| *tag != Tag::End)
+                .map(|(name, tag)| Child { name: Some(name.as_str()), tag }),
+            Frame::List(ref mut elements) => elements
+                .next()
+                .map(|tag| Child { name: None, tag }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use read::*;
+    use std::fs::File;
+    use std::io::BufReader;
+    use std::io::Cursor;
+    use std::io::Read;
+    use std::thread;
+
+    #[test]
+    fn test_round_trip() {
+        let mut contents = Vec::new();
+        File::open("./tests/hello_world.nbt").unwrap().read_to_end(&mut contents).unwrap();
+        let root = read_file(&mut Cursor::new(&contents)).unwrap().root;
+
+        let mut written = Vec::new();
+        write_file(&mut written, &NBTFile::new(Compression::None, root)).unwrap();
+        assert_eq!(contents, written);
+
+        let mut reader = BufReader::new(File::open("./tests/bigtest.nbt").unwrap());
+        let file = read_file(&mut reader).unwrap();
+        let mut written = Vec::new();
+        write_file(&mut written, &file).unwrap();
+        let reread = read_file(&mut Cursor::new(written)).unwrap();
+        assert_eq!(file.root, reread.root);
+
+        for compression in &["None", "Gzip", "Zlib", "Lz4", "Lz4Frame"] {
+            let file = NBTFile::new(compression.parse().unwrap(), reread.root.clone());
+            let mut written = Vec::new();
+            write_file(&mut written, &file).unwrap();
+            let reread = read_file(&mut Cursor::new(written)).unwrap();
+            assert_eq!(reread.compression.to_str(), *compression);
+            assert_eq!(file.root, reread.root);
+        }
+    }
+
+    #[test]
+    fn test_deep_nesting() {
+        const DEPTH: usize = 2000;
+        let mut tag = Tag::List(Vec::new());
+        for _ in 1..DEPTH {
+            tag = Tag::List(vec![tag]);
+        }
+
+        // Neither reading nor writing may use the call stack for nesting, so a
+        // thread with a tiny stack is able to handle the whole structure
+        let options = ReadOptions { max_depth: DEPTH, ..ReadOptions::default() };
+        let reread = thread::Builder::new().stack_size(64 * 1024).spawn(move || {
+            let mut written = Vec::new();
+            write_tag(&mut written, &tag).unwrap();
+            let reread = NbtReader::with_options(Cursor::new(written), options).read_list();
+            (tag, reread)
+        }).unwrap().join().unwrap();
+
+        let (tag, reread) = reread;
+        assert_eq!(tag, reread.unwrap());
+    }
+
+    #[test]
+    fn test_bedrock_level() {
+        let root = Tag::Compound(vec![
+            (String::new(), Tag::Compound(vec![
+                (String::from("LevelName"), Tag::String(String::from("Bedrock level"))),
+                (String::from("StorageVersion"), Tag::Int(10)),
+                (String::new(), Tag::End),
+            ])),
+        ]);
+        let file = NBTFile { storage_version: Some(10), ..NBTFile::new(Compression::None, root) };
+
+        let mut written = Vec::new();
+        write_file(&mut written, &file).unwrap();
+        assert_eq!(&written[..8], &[0x0a, 0x00, 0x00, 0x00, written.len() as u8 - 8, 0x00, 0x00, 0x00]);
+        // The name of the first entry is prefixed by a little-endian length
+        assert_eq!(&written[11..14], &[0x08, 0x09, 0x00]);
+
+        let reread = read_file(&mut Cursor::new(written)).unwrap();
+        assert_eq!(reread.storage_version, Some(10));
+        assert_eq!(reread.root, file.root);
+    }
+}